@@ -0,0 +1,233 @@
+//! an interactive shell for experimenting with an `AVLTree<i64, String>`. every rotation the
+//! tree makes is printed as it happens. type `help` for the commands
+#![allow(clippy::needless_return)]
+
+use avl_rs::{AVLTree, Node, Observer, Rotation};
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::sync::{Arc, Mutex};
+
+type Tree = AVLTree<i64, String>;
+
+const HELP: &str = "\
+put <key> <value>   insert or replace a key
+get <key>           show a key's value
+del <key>           remove a key
+range <from> <to>   list the keys from `from` to `to`, inclusive
+print               draw the tree, with each node's height
+height              show the tree's height
+len                 show the number of keys
+validate            check the AVL invariants
+load <file>         replace the tree with the `key value` lines of a file
+save <file>         write the tree to a file as `key value` lines
+clear               remove every key
+help                show this list
+quit                leave";
+
+/// collects rotations as the tree reports them, to be printed once a command finishes
+#[derive(Clone, Default)]
+struct Rotations(Arc<Mutex<Vec<String>>>);
+
+impl Observer<i64, String> for Rotations {
+    fn on_rotate(&mut self, rotation: Rotation, pivot: &i64, promoted: &i64) {
+        let line = format!("{:?} rotation at {}: {} moves up", rotation, pivot, promoted);
+        self.0.lock().expect("rotation log poisoned").push(line);
+    }
+}
+
+struct Repl {
+    tree: Tree,
+    rotations: Rotations,
+}
+
+impl Repl {
+    fn new() -> Self {
+        let mut repl = Repl { tree: Tree::new(), rotations: Rotations::default() };
+        repl.tree.observe(repl.rotations.clone());
+        return repl;
+    }
+
+    /// run one command line, writing its output to `out`. returns false when it's time to quit
+    fn execute<W: Write>(&mut self, line: &str, out: &mut W) -> Result<bool, Box<dyn Error>> {
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else { return Ok(true) };
+        match command {
+            "put" => {
+                let key = parse_key(words.next())?;
+                // the value is the rest of the line, spaces and all
+                let value = line.trim().splitn(3, char::is_whitespace).nth(2).map(str::trim).unwrap_or("");
+                if value.is_empty() { return Err("usage: put <key> <value>".into()) }
+                if let Some(old) = self.tree.put(key, String::from(value)) {
+                    writeln!(out, "replaced {:?}", old)?;
+                }
+            }
+            "get" => match self.tree.get(parse_key(words.next())?) {
+                Some(value) => writeln!(out, "{}", value)?,
+                None => writeln!(out, "not found")?,
+            },
+            "del" => {
+                let value = self.tree.del(parse_key(words.next())?)?;
+                writeln!(out, "removed {:?}", value)?;
+            }
+            "range" => {
+                let (from, to) = (parse_key(words.next())?, parse_key(words.next())?);
+                for (key, value) in self.tree.range(from..=to) {
+                    writeln!(out, "{} {}", key, value)?;
+                }
+            }
+            "print" => match self.tree.root.as_deref() {
+                Some(root) => draw(root, "", Edge::Root, out)?,
+                None => writeln!(out, "(empty)")?,
+            },
+            "height" => writeln!(out, "{}", self.tree.height())?,
+            "len" => writeln!(out, "{}", self.tree.len())?,
+            "validate" => {
+                self.tree.validate()?;
+                writeln!(out, "ok")?;
+            }
+            "load" => {
+                let path = words.next().ok_or("usage: load <file>")?;
+                let mut pairs = Vec::new();
+                for line in fs::read_to_string(path)?.lines().filter(|line| !line.trim().is_empty()) {
+                    let mut parts = line.trim().splitn(2, char::is_whitespace);
+                    let key = parse_key(parts.next())?;
+                    pairs.push((key, String::from(parts.next().unwrap_or("").trim())));
+                }
+                // built in one pass, so loading doesn't print a rotation per key
+                self.tree = pairs.into_iter().collect();
+                self.tree.observe(self.rotations.clone());
+                writeln!(out, "loaded {} keys", self.tree.len())?;
+            }
+            "save" => {
+                let path = words.next().ok_or("usage: save <file>")?;
+                let mut contents = String::new();
+                for (key, value) in self.tree.iter() {
+                    contents.push_str(&format!("{} {}\n", key, value));
+                }
+                fs::write(path, contents)?;
+                writeln!(out, "saved {} keys", self.tree.len())?;
+            }
+            "clear" => self.tree.clear(),
+            "help" => writeln!(out, "{}", HELP)?,
+            "quit" | "exit" => return Ok(false),
+            _ => return Err(format!("unknown command {:?}, try `help`", command).into())
+        }
+        for rotation in self.rotations.0.lock().expect("rotation log poisoned").drain(..) {
+            writeln!(out, "  {}", rotation)?;
+        }
+        return Ok(true);
+    }
+}
+
+fn parse_key(word: Option<&str>) -> Result<i64, Box<dyn Error>> {
+    let word = word.ok_or("missing key")?;
+    return word.parse().map_err(|_| format!("{:?} is not an integer key", word).into());
+}
+
+/// where a node hangs from its parent
+#[derive(Clone, Copy, PartialEq)]
+enum Edge {
+    Root,
+    Right,
+    Left,
+}
+
+/// draw a subtree on its side, right subtrees above their parents and left ones below
+fn draw<W: Write>(node: &Node<i64, String>, prefix: &str, edge: Edge, out: &mut W) -> io::Result<()> {
+    if let Some(right) = node.right.as_deref() {
+        let bar = if edge == Edge::Left { "│   " } else { "    " };
+        draw(right, &format!("{}{}", prefix, bar), Edge::Right, out)?;
+    }
+    let connector = match edge {
+        Edge::Root => "",
+        Edge::Right => "┌── ",
+        Edge::Left => "└── ",
+    };
+    writeln!(out, "{}{}{} {} (h={})", prefix, connector, node.key, node.data, node.height)?;
+    if let Some(left) = node.left.as_deref() {
+        let bar = if edge == Edge::Right { "│   " } else { "    " };
+        draw(left, &format!("{}{}", prefix, bar), Edge::Left, out)?;
+    }
+    return Ok(());
+}
+
+fn main() {
+    let mut repl = Repl::new();
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+    println!("avl-repl: type `help` for commands");
+    loop {
+        print!("avl> ");
+        stdout.flush().expect("could not write to stdout");
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(err) => { eprintln!("error: {}", err); break; }
+        }
+        match repl.execute(&line, &mut stdout) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(err) => println!("error: {}", err),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(repl: &mut Repl, line: &str) -> String {
+        let mut out = Vec::new();
+        if let Err(err) = repl.execute(line, &mut out) {
+            return format!("error: {}", err);
+        }
+        return String::from_utf8(out).unwrap();
+    }
+
+    #[test]
+    fn test_commands() {
+        let mut repl = Repl::new();
+        assert_eq!(run(&mut repl, "put 1 one"), "");
+        assert_eq!(run(&mut repl, "put 2 two"), "");
+        // the third key in a row unbalances the root
+        assert_eq!(run(&mut repl, "put 3 three and a half"), "  Left rotation at 1: 2 moves up\n");
+        assert_eq!(run(&mut repl, "put 3 three"), "replaced \"three and a half\"\n");
+        assert_eq!(run(&mut repl, "get 3"), "three\n");
+        assert_eq!(run(&mut repl, "get 4"), "not found\n");
+        assert_eq!(run(&mut repl, "range 2 9"), "2 two\n3 three\n");
+        assert_eq!(run(&mut repl, "print"), "    ┌── 3 three (h=1)\n2 two (h=2)\n    └── 1 one (h=1)\n");
+        assert_eq!(run(&mut repl, "height"), "2\n");
+        assert_eq!(run(&mut repl, "validate"), "ok\n");
+        assert_eq!(run(&mut repl, "del 2"), "removed \"two\"\n");
+        assert_eq!(run(&mut repl, "del 2"), "error: could not find node");
+        assert_eq!(run(&mut repl, "len"), "2\n");
+        assert_eq!(run(&mut repl, "put x y"), "error: \"x\" is not an integer key");
+        assert_eq!(run(&mut repl, "put 5"), "error: usage: put <key> <value>");
+        assert_eq!(run(&mut repl, "frobnicate"), "error: unknown command \"frobnicate\", try `help`");
+        assert_eq!(run(&mut repl, ""), "");
+        assert!(!repl.execute("quit", &mut Vec::new()).unwrap());
+    }
+
+    #[test]
+    fn test_save_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tree.txt");
+        let path = path.to_str().unwrap();
+
+        let mut repl = Repl::new();
+        for key in 0..10 {
+            run(&mut repl, &format!("put {} value {}", key, key));
+        }
+        assert_eq!(run(&mut repl, &format!("save {}", path)), "saved 10 keys\n");
+        assert_eq!(run(&mut repl, "clear"), "");
+        assert_eq!(run(&mut repl, "print"), "(empty)\n");
+        assert_eq!(run(&mut repl, &format!("load {}", path)), "loaded 10 keys\n");
+        assert_eq!(run(&mut repl, "get 7"), "value 7\n");
+        // rotations are still reported after a load
+        run(&mut repl, "del 0");
+        assert!(run(&mut repl, "del 1").contains("rotation"));
+    }
+}
@@ -0,0 +1,176 @@
+use crate::Error;
+use core::convert::TryFrom;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// a compact binary encoding for keys and data written outside the process, such as to a
+/// `DurableAVLTree` log. encodings are little-endian and not self-describing, so values
+/// must be decoded as the same type they were encoded from
+pub trait Codec: Sized {
+    /// append the encoding of `self` to `out`
+    fn encode(&self, out: &mut Vec<u8>);
+    /// decode a value from the front of `input`, advancing it past the bytes used
+    fn decode(input: &mut &[u8]) -> Result<Self, Error>;
+}
+
+/// split `len` bytes off the front of `input`
+fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8], Error> {
+    if input.len() < len {
+        return Err(Error::Decode(format!("needed {} bytes, {} left", len, input.len())));
+    }
+    let (head, tail) = input.split_at(len);
+    *input = tail;
+    return Ok(head);
+}
+
+macro_rules! int_codec {
+    ($($int:ty),*) => {$(
+        impl Codec for $int {
+            fn encode(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_le_bytes());
+            }
+
+            fn decode(input: &mut &[u8]) -> Result<Self, Error> {
+                let mut bytes = [0; core::mem::size_of::<$int>()];
+                let len = bytes.len();
+                bytes.copy_from_slice(take(input, len)?);
+                return Ok(<$int>::from_le_bytes(bytes));
+            }
+        }
+    )*}
+}
+
+int_codec!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+// sizes are written as 64 bits so that encodings don't depend on the platform
+impl Codec for usize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u64).encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, Error> {
+        let value = u64::decode(input)?;
+        return usize::try_from(value).map_err(|_| Error::Decode(format!("{} overflows usize", value)));
+    }
+}
+
+impl Codec for isize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as i64).encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, Error> {
+        let value = i64::decode(input)?;
+        return isize::try_from(value).map_err(|_| Error::Decode(format!("{} overflows isize", value)));
+    }
+}
+
+impl Codec for bool {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, Error> {
+        return match u8::decode(input)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            byte => Err(Error::Decode(format!("{} is not a bool", byte)))
+        };
+    }
+}
+
+impl Codec for () {
+    fn encode(&self, _out: &mut Vec<u8>) {}
+
+    fn decode(_input: &mut &[u8]) -> Result<Self, Error> {
+        return Ok(());
+    }
+}
+
+impl Codec for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, Error> {
+        let len = usize::decode(input)?;
+        let bytes = take(input, len)?;
+        return String::from_utf8(bytes.to_vec()).map_err(|err| Error::Decode(format!("{}", err)));
+    }
+}
+
+impl<T: Codec> Codec for Vec<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+        for item in self.iter() {
+            item.encode(out);
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, Error> {
+        let len = usize::decode(input)?;
+        // every item takes at least a byte, except zero-sized ones, so a corrupt length
+        // can't make us reserve more than the input could hold
+        let mut items = Vec::with_capacity(len.min(input.len()));
+        for _ in 0..len {
+            items.push(T::decode(input)?);
+        }
+        return Ok(items);
+    }
+}
+
+impl<T: Codec> Codec for Option<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Some(value) => { out.push(1); value.encode(out); }
+            None => out.push(0)
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, Error> {
+        return match bool::decode(input)? {
+            true => Ok(Some(T::decode(input)?)),
+            false => Ok(None)
+        };
+    }
+}
+
+impl<A: Codec, B: Codec> Codec for (A, B) {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.0.encode(out);
+        self.1.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, Error> {
+        return Ok((A::decode(input)?, B::decode(input)?));
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip<T: Codec + PartialEq + core::fmt::Debug>(value: T) {
+        let mut out = Vec::new();
+        value.encode(&mut out);
+        let mut input = &out[..];
+        assert_eq!(T::decode(&mut input), Ok(value));
+        assert!(input.is_empty());
+        // every proper prefix is too short to decode
+        for len in 0..out.len() {
+            assert!(T::decode(&mut &out[..len]).is_err());
+        }
+    }
+
+    #[quickcheck]
+    fn qc_test_roundtrip(a: i64, b: u8, c: String, d: Vec<(u16, bool)>, e: Option<isize>) {
+        roundtrip(a);
+        roundtrip(b);
+        roundtrip(c);
+        roundtrip(d);
+        roundtrip(e);
+    }
+}
@@ -0,0 +1,24 @@
+use core::cmp::Ordering;
+
+/// a total order over keys. trees use it in place of `Ord` so that keys can be sorted in
+/// reverse, case-insensitively or by a projected field without wrapping them
+pub trait Compare<K: ?Sized> {
+    fn compare(&self, a: &K, b: &K) -> Ordering;
+}
+
+/// the order given by the key type's `Ord` implementation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Natural;
+
+impl<K: Ord + ?Sized> Compare<K> for Natural {
+    fn compare(&self, a: &K, b: &K) -> Ordering {
+        return a.cmp(b);
+    }
+}
+
+/// any `Fn(&K, &K) -> Ordering` closure can be used as a comparator
+impl<K: ?Sized, F: Fn(&K, &K) -> Ordering> Compare<K> for F {
+    fn compare(&self, a: &K, b: &K) -> Ordering {
+        return self(a, b);
+    }
+}
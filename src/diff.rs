@@ -0,0 +1,238 @@
+use crate::{AVLTree, Compare, Node};
+use allocator_api2::alloc::Allocator;
+use core::cmp::Ordering::{Equal, Greater, Less};
+use core::fmt;
+use alloc::vec::Vec;
+
+/// one difference between two trees, as a step that turns the first into the second
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Change<K, D> {
+    /// the key is only in the second tree
+    Added(K, D),
+    /// the key is only in the first tree
+    Removed(K, D),
+    /// the key is in both, with the first tree's data and then the second's
+    Modified(K, D, D),
+}
+
+impl<K, D> Change<K, D> {
+    pub fn key(&self) -> &K {
+        return match self {
+            Change::Added(key, _) | Change::Removed(key, _) | Change::Modified(key, _, _) => key
+        };
+    }
+}
+
+impl<K, D, C, A> AVLTree<K,D,C,A>
+where K: Clone + fmt::Debug, D: Clone + fmt::Debug + PartialEq, C: Compare<K>, A: Allocator + Clone
+{
+    /// the changes that turn this tree into `other`, in key order. both trees are walked
+    /// at most once, in O(n + m), and keys are matched with this tree's comparator. a tree
+    /// diffed against itself is skipped outright. with the `merkle` feature, so is any pair
+    /// of subtrees whose cached digests match, which makes diffing a tree against a lightly
+    /// edited clone cheap once `root_hash` has been called on both. like `root_hash`, this
+    /// trusts digests left stale by data changed in place
+    pub fn diff<'a>(&'a self, other: &'a Self) -> Diff<'a, K, D, C, A> {
+        return Diff {
+            a: self.root.as_deref().map(Pending::Tree).into_iter().collect(),
+            b: other.root.as_deref().map(Pending::Tree).into_iter().collect(),
+            cmp: self.comparator(),
+        };
+    }
+
+    /// replay a changeset, such as one from `diff`. added and modified keys are put and
+    /// removed keys deleted, so applying a changeset twice is the same as applying it once
+    pub fn apply<I: IntoIterator<Item = Change<K, D>>>(&mut self, patch: I) {
+        for change in patch {
+            match change {
+                Change::Added(key, data) | Change::Modified(key, _, data) => { self.put(key, data); }
+                Change::Removed(key, _) => { let _ = self.del(key); }
+            }
+        }
+    }
+}
+
+/// what is left of one side of a diff, with the next in order on top of the stack
+enum Pending<'a, K, D, A: Allocator> {
+    /// a whole subtree, not yet taken apart
+    Tree(&'a Node<K,D,A>),
+    /// a single node's pair, its subtrees having been queued separately
+    Item(&'a Node<K,D,A>),
+}
+
+/// whether two subtrees are known to hold the same pairs without walking them
+fn same<K, D, A: Allocator>(a: &Node<K,D,A>, b: &Node<K,D,A>) -> bool {
+    #[cfg(feature = "merkle")]
+    if a.same_digest(b) { return true }
+    return core::ptr::eq(a, b);
+}
+
+/// merge-walks two trees, yielding what differs between them
+pub struct Diff<'a, K, D, C, A: Allocator> {
+    a: Vec<Pending<'a, K, D, A>>,
+    b: Vec<Pending<'a, K, D, A>>,
+    cmp: &'a C,
+}
+
+impl<'a, K, D, C, A: Allocator> Diff<'a, K, D, C, A> {
+    /// replace the subtree on top of a side by its left subtree, its own pair and its right
+    /// subtree
+    fn expand(side: &mut Vec<Pending<'a, K, D, A>>) {
+        if let Some(Pending::Tree(node)) = side.pop() {
+            side.extend(node.right.as_deref().map(Pending::Tree));
+            side.push(Pending::Item(node));
+            side.extend(node.left.as_deref().map(Pending::Tree));
+        }
+    }
+
+    /// take the subtrees at the top of both sides apart until each side has a pair on top,
+    /// dropping pairs of subtrees that hold the same pairs along the way
+    fn settle(&mut self) {
+        loop {
+            match (self.a.last(), self.b.last()) {
+                (Some(Pending::Tree(a)), Some(Pending::Tree(b))) => {
+                    if same(a, b) {
+                        self.a.pop();
+                        self.b.pop();
+                    } else if a.height >= b.height {
+                        // the taller side is taken apart first, in the hope its pieces line up
+                        // with the other side's whole subtrees
+                        Self::expand(&mut self.a);
+                    } else {
+                        Self::expand(&mut self.b);
+                    }
+                }
+                (Some(Pending::Tree(_)), _) => Self::expand(&mut self.a),
+                (_, Some(Pending::Tree(_))) => Self::expand(&mut self.b),
+                _ => return
+            }
+        }
+    }
+
+    /// the pair on top of a side, once `settle` has made sure it isn't a subtree
+    fn next_item(side: &mut Vec<Pending<'a, K, D, A>>) -> Option<(&'a K, &'a D)> {
+        let node = match side.pop()? {
+            Pending::Tree(node) | Pending::Item(node) => node
+        };
+        return Some((&node.key, &node.data));
+    }
+}
+
+impl<'a, K, D, C, A> Iterator for Diff<'a, K, D, C, A>
+where K: Clone, D: Clone + PartialEq, C: Compare<K>, A: Allocator
+{
+    type Item = Change<K, D>;
+
+    fn next(&mut self) -> Option<Change<K, D>> {
+        loop {
+            self.settle();
+            match (self.a.last(), self.b.last()) {
+                (Some(Pending::Item(a)), Some(Pending::Item(b))) => match self.cmp.compare(&a.key, &b.key) {
+                    Less => {
+                        let (key, data) = Self::next_item(&mut self.a)?;
+                        return Some(Change::Removed(key.clone(), data.clone()));
+                    }
+                    Greater => {
+                        let (key, data) = Self::next_item(&mut self.b)?;
+                        return Some(Change::Added(key.clone(), data.clone()));
+                    }
+                    Equal => {
+                        let (key, old) = Self::next_item(&mut self.a)?;
+                        let (_, new) = Self::next_item(&mut self.b)?;
+                        if old != new {
+                            return Some(Change::Modified(key.clone(), old.clone(), new.clone()));
+                        }
+                    }
+                },
+                (Some(_), None) => {
+                    let (key, data) = Self::next_item(&mut self.a)?;
+                    return Some(Change::Removed(key.clone(), data.clone()));
+                }
+                _ => {
+                    let (key, data) = Self::next_item(&mut self.b)?;
+                    return Some(Change::Added(key.clone(), data.clone()));
+                }
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_diff() {
+        let a = AVLTree::from([(1, "a"), (2, "b"), (3, "c")]);
+        let b = AVLTree::from([(2, "b"), (3, "C"), (4, "d")]);
+        assert_eq!(a.diff(&b).collect::<Vec<_>>(), vec![
+            Change::Removed(1, "a"),
+            Change::Modified(3, "c", "C"),
+            Change::Added(4, "d")]);
+        assert_eq!(a.diff(&a).next(), None);
+        assert_eq!(a.diff(&b).map(|change| *change.key()).collect::<Vec<_>>(), vec![1, 3, 4]);
+    }
+
+    #[quickcheck]
+    fn qc_test_diff_apply(xs: BTreeMap<u8, u8>, ys: BTreeMap<u8, u8>) {
+        let a = AVLTree::from(xs.clone());
+        let b = AVLTree::from(ys.clone());
+        let changes: Vec<Change<u8, u8>> = a.diff(&b).collect();
+        for change in changes.iter() {
+            match change {
+                Change::Added(key, data) => assert!(!xs.contains_key(key) && ys[key] == *data),
+                Change::Removed(key, data) => assert!(xs[key] == *data && !ys.contains_key(key)),
+                Change::Modified(key, old, new) => assert!(xs[key] == *old && ys[key] == *new && old != new),
+            }
+        }
+        let unchanged = xs.iter().filter(|(key, data)| ys.get(key) == Some(data)).count();
+        assert_eq!(changes.len() + unchanged, xs.keys().chain(ys.keys()).collect::<std::collections::BTreeSet<_>>().len());
+
+        let mut patched = a.clone();
+        patched.apply(changes.clone());
+        assert_eq!(patched, b);
+        assert_eq!(patched.len(), b.len());
+        assert_eq!(patched.validate(), Ok(()));
+        patched.apply(changes);
+        assert_eq!(patched, b);
+    }
+
+    /// a clone shares its original's shape, so after a few edits most subtrees still match
+    #[cfg(feature = "merkle")]
+    #[quickcheck]
+    fn qc_test_diff_edited_clone(xs: BTreeMap<u8, u8>, edits: Vec<(bool, u8, u8)>) {
+        let a = AVLTree::from(xs);
+        let mut b = a.clone();
+        for (put, key, data) in edits {
+            if put { b.put(key, data); } else { let _ = b.del(key); }
+        }
+        a.root_hash();
+        b.root_hash();
+        let skipped: Vec<Change<u8, u8>> = a.diff(&b).collect();
+
+        let (xs, ys) = (BTreeMap::from(a.clone()), BTreeMap::from(b.clone()));
+        let walked: Vec<Change<u8, u8>> = AVLTree::from(xs).diff(&AVLTree::from(ys)).collect();
+        assert_eq!(skipped, walked);
+    }
+
+    #[cfg(feature = "merkle")]
+    #[test]
+    fn test_diff_skips_matching_digests() {
+        let a: AVLTree<u16, u16> = (0..1000).map(|key| (key, key)).collect();
+        let mut b = a.clone();
+        b.put(500, 0);
+        a.root_hash();
+        b.root_hash();
+        assert_eq!(a.diff(&b).collect::<Vec<_>>(), vec![Change::Modified(500, 500, 0)]);
+
+        // data changed in place leaves its digests stale, so the diff trusts them and skips
+        // right over the change. this is the only way to see that subtrees were skipped
+        b.root.as_mut().unwrap().get_mut(&7).unwrap().data = 0;
+        assert_eq!(a.diff(&b).collect::<Vec<_>>(), vec![Change::Modified(500, 500, 0)]);
+        let mut fresh = b.clone();
+        fresh.put(7, 0);
+        assert_eq!(a.diff(&fresh).count(), 2);
+    }
+}
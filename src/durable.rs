@@ -0,0 +1,482 @@
+use crate::{AVLTree, Codec, Error};
+use core::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const LOG: &str = "wal";
+const SNAPSHOT: &str = "snapshot";
+const SNAPSHOT_TMP: &str = "snapshot.tmp";
+
+const PUT: u8 = 1;
+const DEL: u8 = 2;
+
+// each record is framed as its payload length and the payload's crc32, both u32, then the payload
+const HEADER: usize = 8;
+
+/// when appended log records are forced out to stable storage
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// fsync after every record, so nothing acknowledged is lost even to a power failure
+    Always,
+    /// fsync after every `n` records
+    Every(usize),
+    /// leave flushing to the operating system. records survive the process crashing, but
+    /// not the machine
+    Never,
+}
+
+/// settings for `DurableAVLTree::open_with`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DurableOptions {
+    pub sync: SyncPolicy,
+    /// fold the log into a fresh snapshot once it holds this many records. with `None` the
+    /// log only shrinks when `compact` is called
+    pub compact_after: Option<usize>,
+}
+
+impl Default for DurableOptions {
+    fn default() -> Self {
+        return Self { sync: SyncPolicy::Always, compact_after: Some(4096) };
+    }
+}
+
+/// an `AVLTree` kept in a directory on disk. every change is appended to a write-ahead log
+/// before it is applied in memory, and opening the directory replays the latest snapshot
+/// and then the log
+pub struct DurableAVLTree<K, D> {
+    tree: AVLTree<K,D>,
+    dir: PathBuf,
+    log: File,
+    options: DurableOptions,
+    // records in the log, and records written since the last fsync
+    records: usize,
+    unsynced: usize,
+    // length of the log in bytes, up to the end of its last whole record
+    end: u64,
+    // why the last automatic compaction failed, until one succeeds
+    compaction_error: Option<Error>,
+    // make the next fsync fail, to test what a failed append leaves behind
+    #[cfg(test)]
+    fail_sync: bool,
+}
+
+impl<K, D> DurableAVLTree<K, D>
+where K: Ord + Eq + Clone + fmt::Debug + Codec, D: Clone + fmt::Debug + Codec
+{
+    /// open or create a tree in `dir` with the default options
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, Error> {
+        return Self::open_with(dir, DurableOptions::default());
+    }
+
+    /// open or create a tree in `dir`. a record cut short or corrupted by a crash ends the
+    /// log: it and anything after it are discarded
+    pub fn open_with<P: AsRef<Path>>(dir: P, options: DurableOptions) -> Result<Self, Error> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let mut tree = AVLTree::new();
+
+        // snapshots are renamed into place once complete, so a bad one isn't a torn write
+        let snapshot = read_or_empty(&dir.join(SNAPSHOT))?;
+        let (records, valid) = split_records(&snapshot);
+        if valid < snapshot.len() {
+            return Err(Error::Decode(format!("snapshot is corrupt after {} bytes", valid)));
+        }
+        for payload in records {
+            apply(&mut tree, payload)?;
+        }
+
+        let path = dir.join(LOG);
+        let log = read_or_empty(&path)?;
+        let (records, valid) = split_records(&log);
+        for payload in records.iter() {
+            apply(&mut tree, payload)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        if valid < log.len() {
+            log::warn!("discarding {} bytes of torn log in {:?}", log.len() - valid, dir);
+            file.set_len(valid as u64)?;
+            file.sync_all()?;
+        }
+
+        let mut durable = Self {
+            tree, dir, log: file, options, records: records.len(), unsynced: 0, end: valid as u64,
+            compaction_error: None,
+            #[cfg(test)]
+            fail_sync: false,
+        };
+        durable.maybe_compact();
+        return Ok(durable);
+    }
+
+    /// the tree as it stands in memory
+    pub fn tree(&self) -> &AVLTree<K,D> {
+        return &self.tree;
+    }
+
+    pub fn get(&self, key: K) -> Option<D> {
+        return self.tree.get(key);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.tree.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.tree.is_empty();
+    }
+
+    /// why the last automatic compaction failed, if none has succeeded since. a failed
+    /// compaction doesn't fail the write that triggered it: the log just keeps growing, and
+    /// compaction is tried again after the next write
+    pub fn compaction_error(&self) -> Option<&Error> {
+        return self.compaction_error.as_ref();
+    }
+
+    /// log a key/data pair, then insert it. returns the data it replaced, if any. if the
+    /// record can't be written and synced, it is cut back out of the log and the tree is
+    /// left as it was
+    pub fn put(&mut self, key: K, data: D) -> Result<Option<D>, Error> {
+        let mut payload = vec![PUT];
+        key.encode(&mut payload);
+        data.encode(&mut payload);
+        self.append(&payload)?;
+        let old = self.tree.put(key, data);
+        self.maybe_compact();
+        return Ok(old);
+    }
+
+    /// log the deletion of a key, then delete it. nothing is logged for a missing key, and
+    /// like `put`, nothing changes if logging fails
+    pub fn del(&mut self, key: K) -> Result<D, Error> {
+        if self.tree.find(&key).is_none() { return Err(Error::KeyNotFound) }
+        let mut payload = vec![DEL];
+        key.encode(&mut payload);
+        self.append(&payload)?;
+        let old = self.tree.del(key)?;
+        self.maybe_compact();
+        return Ok(old);
+    }
+
+    /// fsync any records the sync policy has left unflushed
+    pub fn sync(&mut self) -> Result<(), Error> {
+        #[cfg(test)]
+        if core::mem::take(&mut self.fail_sync) {
+            return Err(Error::Io(String::from("injected fsync failure")));
+        }
+        self.log.sync_data()?;
+        self.unsynced = 0;
+        return Ok(());
+    }
+
+    /// write the whole tree to a new snapshot and empty the log
+    pub fn compact(&mut self) -> Result<(), Error> {
+        let mut bytes = Vec::new();
+        for (key, data) in self.tree.iter() {
+            let mut payload = vec![PUT];
+            key.encode(&mut payload);
+            data.encode(&mut payload);
+            frame(&payload, &mut bytes);
+        }
+        let tmp = self.dir.join(SNAPSHOT_TMP);
+        let mut file = File::create(&tmp)?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        fs::rename(&tmp, self.dir.join(SNAPSHOT))?;
+        sync_dir(&self.dir)?;
+        // a crash before the log is emptied replays it over a snapshot that already holds
+        // its changes, which ends in the same tree
+        self.log.set_len(0)?;
+        self.end = 0;
+        self.records = 0;
+        self.log.sync_all()?;
+        self.unsynced = 0;
+        return Ok(());
+    }
+
+    fn append(&mut self, payload: &[u8]) -> Result<(), Error> {
+        let mut bytes = Vec::with_capacity(HEADER + payload.len());
+        frame(payload, &mut bytes);
+        if let Err(err) = self.write_record(&bytes) {
+            // the change is reported as failed and never reaches the tree, so the record, or
+            // whatever part of it was written, must not be replayed by the next open either
+            if let Err(undo) = self.log.set_len(self.end) {
+                log::error!("could not cut a failed record out of the log in {:?}: {}", self.dir, undo);
+            }
+            return Err(err);
+        }
+        self.end += bytes.len() as u64;
+        self.records += 1;
+        return Ok(());
+    }
+
+    fn write_record(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.log.write_all(bytes)?;
+        self.unsynced += 1;
+        match self.options.sync {
+            SyncPolicy::Always => self.sync()?,
+            SyncPolicy::Every(n) if self.unsynced >= n => self.sync()?,
+            _ => ()
+        }
+        return Ok(());
+    }
+
+    /// compact if the log has reached its limit. by now the change is durable and applied,
+    /// so a failure is only recorded for `compaction_error`, never returned
+    fn maybe_compact(&mut self) {
+        match self.options.compact_after {
+            Some(limit) if self.records >= limit => (),
+            _ => return
+        }
+        self.compaction_error = match self.compact() {
+            Ok(()) => None,
+            Err(err) => {
+                log::warn!("could not compact {:?}, keeping the log: {}", self.dir, err);
+                Some(err)
+            }
+        };
+    }
+}
+
+/// replay one record against the tree
+fn apply<K, D>(tree: &mut AVLTree<K,D>, mut payload: &[u8]) -> Result<(), Error>
+where K: Ord + Eq + Clone + fmt::Debug + Codec, D: Clone + fmt::Debug + Codec
+{
+    let input = &mut payload;
+    match u8::decode(input)? {
+        PUT => { let key = K::decode(input)?; tree.put(key, D::decode(input)?); }
+        // the key was present when the record was written, but a replay over a newer
+        // snapshot may have removed it already
+        DEL => { let _ = tree.del(K::decode(input)?); }
+        op => return Err(Error::Decode(format!("unknown log operation {}", op)))
+    }
+    if !input.is_empty() {
+        return Err(Error::Decode(format!("{} bytes left over in a log record", input.len())));
+    }
+    return Ok(());
+}
+
+fn frame(payload: &[u8], out: &mut Vec<u8>) {
+    out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    out.extend_from_slice(&crc32(payload).to_le_bytes());
+    out.extend_from_slice(payload);
+}
+
+/// the payloads of the intact records at the start of `bytes`, and the length they cover.
+/// the first short or corrupt record ends the list
+fn split_records(mut bytes: &[u8]) -> (Vec<&[u8]>, usize) {
+    let mut records = Vec::new();
+    let mut valid = 0;
+    while bytes.len() >= HEADER {
+        let len = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+        let crc = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        let Some(payload) = bytes.get(HEADER..HEADER + len) else { break };
+        if crc32(payload) != crc { break }
+        records.push(payload);
+        valid += HEADER + len;
+        bytes = &bytes[HEADER + len..];
+    }
+    return (records, valid);
+}
+
+/// the IEEE crc32 used by zip and ethernet
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    return !crc;
+}
+
+fn read_or_empty(path: &Path) -> Result<Vec<u8>, Error> {
+    return match fs::read(path) {
+        Ok(bytes) => Ok(bytes),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err.into())
+    };
+}
+
+/// make a rename in `dir` durable
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> Result<(), Error> {
+    File::open(dir)?.sync_all()?;
+    return Ok(());
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> Result<(), Error> {
+    return Ok(());
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use tempfile::tempdir;
+
+    const NO_COMPACTION: DurableOptions = DurableOptions { sync: SyncPolicy::Never, compact_after: None };
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_reopen() {
+        let dir = tempdir().unwrap();
+        let mut tree = DurableAVLTree::open(dir.path()).unwrap();
+        assert_eq!(tree.put(1, String::from("a")), Ok(None));
+        assert_eq!(tree.put(2, String::from("b")), Ok(None));
+        assert_eq!(tree.put(1, String::from("c")), Ok(Some(String::from("a"))));
+        assert_eq!(tree.del(2), Ok(String::from("b")));
+        assert_eq!(tree.del(2), Err(Error::KeyNotFound));
+        drop(tree);
+
+        let tree: DurableAVLTree<i32, String> = DurableAVLTree::open(dir.path()).unwrap();
+        assert_eq!(tree.tree().items(), vec![(1, String::from("c"))]);
+    }
+
+    #[test]
+    fn test_compaction() {
+        let dir = tempdir().unwrap();
+        let options = DurableOptions { sync: SyncPolicy::Every(3), compact_after: Some(10) };
+        let mut tree = DurableAVLTree::open_with(dir.path(), options).unwrap();
+        for key in 0..25u32 { tree.put(key % 7, key).unwrap(); }
+        tree.del(3).unwrap();
+        // 26 records, compacted at 10 and 20
+        assert_eq!(tree.records, 6);
+        assert!(dir.path().join(SNAPSHOT).exists());
+        let expected = tree.tree().items();
+        drop(tree);
+
+        let mut tree = DurableAVLTree::<u32, u32>::open_with(dir.path(), options).unwrap();
+        assert_eq!(tree.tree().items(), expected);
+        tree.compact().unwrap();
+        assert_eq!(fs::metadata(dir.path().join(LOG)).unwrap().len(), 0);
+        drop(tree);
+        let tree = DurableAVLTree::<u32, u32>::open_with(dir.path(), options).unwrap();
+        assert_eq!(tree.tree().items(), expected);
+    }
+
+    #[test]
+    fn test_failed_sync() {
+        let dir = tempdir().unwrap();
+        let options = DurableOptions { sync: SyncPolicy::Always, compact_after: None };
+        let mut tree = DurableAVLTree::open_with(dir.path(), options).unwrap();
+        tree.put(1u8, 1u8).unwrap();
+        tree.put(2, 2).unwrap();
+        let path = dir.path().join(LOG);
+        let len = fs::metadata(&path).unwrap().len();
+
+        // the record is written but never synced, so neither the log nor the tree keeps it
+        tree.fail_sync = true;
+        assert!(matches!(tree.put(3, 3), Err(Error::Io(_))));
+        tree.fail_sync = true;
+        assert!(matches!(tree.del(1), Err(Error::Io(_))));
+        assert_eq!(tree.tree().items(), vec![(1, 1), (2, 2)]);
+        assert_eq!(fs::metadata(&path).unwrap().len(), len);
+        assert_eq!(tree.records, 2);
+
+        tree.put(4, 4).unwrap();
+        drop(tree);
+        let tree = DurableAVLTree::<u8, u8>::open_with(dir.path(), options).unwrap();
+        assert_eq!(tree.tree().items(), vec![(1, 1), (2, 2), (4, 4)]);
+    }
+
+    #[test]
+    fn test_failed_compaction() {
+        let dir = tempdir().unwrap();
+        let options = DurableOptions { sync: SyncPolicy::Always, compact_after: Some(2) };
+        let mut tree = DurableAVLTree::open_with(dir.path(), options).unwrap();
+        tree.put(1u8, 1u8).unwrap();
+
+        // a directory where the snapshot is written makes compaction fail after the append
+        let blocker = dir.path().join(SNAPSHOT_TMP);
+        fs::create_dir(&blocker).unwrap();
+        assert_eq!(tree.put(2, 2), Ok(None));
+        assert!(matches!(tree.compaction_error(), Some(Error::Io(_))));
+        assert_eq!(tree.del(1), Ok(1));
+        assert_eq!(tree.records, 3);
+        assert_eq!(tree.tree().items(), vec![(2, 2)]);
+        drop(tree);
+
+        let mut tree = DurableAVLTree::<u8, u8>::open_with(dir.path(), NO_COMPACTION).unwrap();
+        assert_eq!(tree.tree().items(), vec![(2, 2)]);
+        drop(tree);
+
+        fs::remove_dir(&blocker).unwrap();
+        tree = DurableAVLTree::open_with(dir.path(), options).unwrap();
+        assert_eq!(tree.compaction_error(), None);
+        assert_eq!(tree.records, 0);
+        tree.put(3, 3).unwrap();
+        drop(tree);
+        let tree = DurableAVLTree::<u8, u8>::open_with(dir.path(), NO_COMPACTION).unwrap();
+        assert_eq!(tree.tree().items(), vec![(2, 2), (3, 3)]);
+    }
+
+    #[test]
+    fn test_corrupt_tail() {
+        let dir = tempdir().unwrap();
+        let mut tree = DurableAVLTree::open_with(dir.path(), NO_COMPACTION).unwrap();
+        tree.put(1u8, 1u8).unwrap();
+        tree.put(2, 2).unwrap();
+        drop(tree);
+
+        // flip the last byte, which belongs to the second record's payload
+        let path = dir.path().join(LOG);
+        let mut bytes = fs::read(&path).unwrap();
+        *bytes.last_mut().unwrap() ^= 0xff;
+        fs::write(&path, &bytes).unwrap();
+
+        let mut tree = DurableAVLTree::<u8, u8>::open_with(dir.path(), NO_COMPACTION).unwrap();
+        assert_eq!(tree.tree().items(), vec![(1, 1)]);
+        tree.put(3, 3).unwrap();
+        drop(tree);
+        let tree = DurableAVLTree::<u8, u8>::open_with(dir.path(), NO_COMPACTION).unwrap();
+        assert_eq!(tree.tree().items(), vec![(1, 1), (3, 3)]);
+    }
+
+    /// cut the log anywhere: reopening must give the tree as it was after the last whole record
+    #[quickcheck]
+    fn qc_test_torn_log(ops: Vec<(bool, u8, u16)>, cut: usize, compact_at: Option<u8>) {
+        let dir = tempdir().unwrap();
+        let mut tree = DurableAVLTree::open_with(dir.path(), NO_COMPACTION).unwrap();
+        let path = dir.path().join(LOG);
+        // the log's length after each record, and the tree each prefix of the log gives
+        let mut ends = vec![0];
+        let mut states = vec![BTreeMap::new()];
+        let compact_at = compact_at.map(|at| at as usize % (ops.len() + 1));
+        for (i, (put, key, data)) in ops.into_iter().enumerate() {
+            if compact_at == Some(i) {
+                tree.compact().unwrap();
+                let state = states.pop().unwrap();
+                ends = vec![0];
+                states = vec![state];
+            }
+            let logged = if put { tree.put(key, data).is_ok() } else { tree.del(key).is_ok() };
+            if logged {
+                ends.push(fs::metadata(&path).unwrap().len());
+                states.push(BTreeMap::from(tree.tree().clone()));
+            }
+        }
+        drop(tree);
+
+        let cut = (cut as u64) % (ends.last().unwrap() + 1);
+        OpenOptions::new().write(true).open(&path).unwrap().set_len(cut).unwrap();
+        let whole = ends.iter().filter(|end| **end <= cut).count() - 1;
+
+        let mut tree = DurableAVLTree::<u8, u16>::open_with(dir.path(), NO_COMPACTION).unwrap();
+        assert_eq!(BTreeMap::from(tree.tree().clone()), states[whole]);
+        assert_eq!(tree.tree().validate(), Ok(()));
+        // the torn tail is gone, so new records are readable after it
+        tree.put(0, 0).unwrap();
+        let expected = tree.tree().items();
+        drop(tree);
+        let tree = DurableAVLTree::<u8, u16>::open_with(dir.path(), NO_COMPACTION).unwrap();
+        assert_eq!(tree.tree().items(), expected);
+    }
+}
@@ -0,0 +1,49 @@
+use core::fmt;
+use alloc::string::String;
+#[cfg(feature = "std")]
+use alloc::format;
+
+/// errors returned by fallible tree operations
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// the requested key is not present in the tree
+    KeyNotFound,
+    /// the key is already present and the operation does not allow replacing it
+    DuplicateKey,
+    /// input that was required to be sorted by key was not
+    Unsorted,
+    /// the tree is not a valid AVL tree: keys out of order, bad heights or a subtree out of balance
+    InvariantViolation(String),
+    /// a serialized tree or record could not be decoded
+    Decode(String),
+    /// the named checkpoint was never set, or the history needed to reach it was discarded
+    CheckpointUnavailable(String),
+    /// reading or writing a file failed
+    Io(String),
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::KeyNotFound => write!(f, "could not find node"),
+            Error::DuplicateKey => write!(f, "duplicate key"),
+            Error::Unsorted => write!(f, "keys are not in sorted order"),
+            Error::InvariantViolation(msg) => write!(f, "invariant violation: {}", msg),
+            Error::Decode(msg) => write!(f, "decode error: {}", msg),
+            Error::CheckpointUnavailable(name) => write!(f, "checkpoint {:?} is unavailable", name),
+            Error::Io(msg) => write!(f, "i/o error: {}", msg),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        return Error::Io(format!("{}", err));
+    }
+}
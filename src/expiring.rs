@@ -0,0 +1,240 @@
+use crate::{AVLSet, AVLTree};
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering::Relaxed};
+use core::time::Duration;
+use alloc::vec::Vec;
+use allocator_api2::boxed::Box;
+
+/// a monotonic source of time, measured from whatever starting point the clock chooses
+pub trait Clock {
+    fn now(&self) -> Duration;
+}
+
+/// the real monotonic clock, counting from when it was created
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+pub struct SystemClock {
+    start: std::time::Instant,
+}
+
+#[cfg(feature = "std")]
+impl SystemClock {
+    pub fn new() -> Self {
+        return Self { start: std::time::Instant::now() };
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for SystemClock {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        return self.start.elapsed();
+    }
+}
+
+/// a clock that only moves when told to, for tests and simulations. starts at zero
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    nanos: AtomicU64,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.nanos.fetch_add(by.as_nanos() as u64, Relaxed);
+    }
+
+    pub fn set(&self, now: Duration) {
+        self.nanos.store(now.as_nanos() as u64, Relaxed);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        return Duration::from_nanos(self.nanos.load(Relaxed));
+    }
+}
+
+/// an ordered map whose entries expire a fixed time after they are inserted. expired
+/// entries are hidden from lookups straight away, but only freed by `purge_expired`
+pub struct ExpiringMap<K, V, T: Clock> {
+    // each value with its deadline
+    entries: AVLTree<K, (V, Duration)>,
+    // the same entries ordered by deadline, so the next to expire is always the first
+    deadlines: AVLSet<(Duration, K)>,
+    clock: T,
+}
+
+#[cfg(feature = "std")]
+impl<K, V> ExpiringMap<K, V, SystemClock>
+where K: Ord + Eq + Clone + fmt::Debug, V: Clone + fmt::Debug
+{
+    pub fn new() -> Self {
+        return Self::with_clock(SystemClock::new());
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K, V> Default for ExpiringMap<K, V, SystemClock>
+where K: Ord + Eq + Clone + fmt::Debug, V: Clone + fmt::Debug
+{
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl<K, V, T: Clock> ExpiringMap<K, V, T>
+where K: Ord + Eq + Clone + fmt::Debug, V: Clone + fmt::Debug
+{
+    /// create an empty map that reads the time from `clock`
+    pub fn with_clock(clock: T) -> Self {
+        return Self { entries: AVLTree::new(), deadlines: AVLSet::new(), clock };
+    }
+
+    pub fn clock(&self) -> &T {
+        return &self.clock;
+    }
+
+    /// insert a value that expires `ttl` from now. if the key was already present its
+    /// value and deadline are replaced, and the old value returned unless it had expired
+    pub fn insert_with_ttl(&mut self, key: K, value: V, ttl: Duration) -> Option<V> {
+        let now = self.clock.now();
+        let deadline = now.checked_add(ttl).unwrap_or(Duration::MAX);
+        let old = self.entries.put(key.clone(), (value, deadline));
+        // the old deadline goes first, in case it equals the new one
+        if let Some((_, old_deadline)) = &old {
+            self.deadlines.remove(&(*old_deadline, key.clone()));
+        }
+        self.deadlines.insert((deadline, key));
+        let (old, old_deadline) = old?;
+        return if now < old_deadline { Some(old) } else { None };
+    }
+
+    /// the value stored under a key, if it has not expired
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let node = self.entries.find(key)?;
+        let (value, deadline) = &node.data;
+        return if self.clock.now() < *deadline { Some(value) } else { None };
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        return self.get(key).is_some();
+    }
+
+    /// how long until a key expires, or `None` if it is missing or already expired
+    pub fn ttl(&self, key: &K) -> Option<Duration> {
+        let node = self.entries.find(key)?;
+        return node.data.1.checked_sub(self.clock.now()).filter(|left| !left.is_zero());
+    }
+
+    /// remove a key, returning its value unless it had expired
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let (value, deadline) = Box::into_inner(self.entries.remove_node(key).ok()?).into_pair().1;
+        self.deadlines.remove(&(deadline, key.clone()));
+        return if self.clock.now() < deadline { Some(value) } else { None };
+    }
+
+    /// free every entry whose deadline is at or before `now` and return them in deadline
+    /// order. only the expired entries are visited, so this takes O(log n) per entry
+    /// removed rather than a scan of the whole map
+    pub fn purge_expired(&mut self, now: Duration) -> alloc::vec::IntoIter<(K, V)> {
+        let mut expired = Vec::new();
+        while let Some((deadline, key)) = self.deadlines.first().cloned() {
+            if deadline > now { break }
+            self.deadlines.remove(&(deadline, key.clone()));
+            if let Ok(node) = self.entries.remove_node(&key) {
+                expired.push((key, Box::into_inner(node).into_pair().1.0));
+            }
+        }
+        return expired.into_iter();
+    }
+
+    /// number of entries, including expired ones that have not been purged yet, in O(1)
+    pub fn len(&self) -> usize {
+        return self.entries.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.entries.is_empty();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn test_expiry() {
+        let mut map = ExpiringMap::with_clock(ManualClock::new());
+        assert_eq!(map.insert_with_ttl("a", 1, 10 * SECOND), None);
+        assert_eq!(map.insert_with_ttl("b", 2, 20 * SECOND), None);
+        assert_eq!(map.insert_with_ttl("c", 3, 30 * SECOND), None);
+
+        map.clock().advance(15 * SECOND);
+        assert_eq!(map.get(&"a"), None);
+        assert_eq!(map.get(&"b"), Some(&2));
+        assert_eq!(map.ttl(&"b"), Some(5 * SECOND));
+        assert_eq!(map.len(), 3);
+
+        // replacing a live entry resets its deadline
+        assert_eq!(map.insert_with_ttl("b", 20, 20 * SECOND), Some(2));
+        // replacing an expired one hands nothing back
+        assert_eq!(map.insert_with_ttl("a", 10, 20 * SECOND), None);
+
+        map.clock().advance(15 * SECOND);
+        let now = map.clock().now();
+        assert_eq!(map.purge_expired(now).collect::<Vec<_>>(), vec![("c", 3)]);
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.remove(&"a"), Some(10));
+        assert_eq!(map.purge_expired(now + 5 * SECOND).collect::<Vec<_>>(), vec![("b", 20)]);
+        assert!(map.is_empty());
+    }
+
+    /// the map must agree with a model that scans every entry
+    #[quickcheck]
+    fn qc_test_purge(ops: Vec<(u8, u8, u8)>) {
+        let mut map = ExpiringMap::with_clock(ManualClock::new());
+        let mut model: BTreeMap<u8, (u8, Duration)> = BTreeMap::new();
+        for (key, value, ttl) in ops {
+            let now = map.clock().now();
+            match ttl % 4 {
+                0 => {
+                    let mut expired: Vec<(u8, u8)> = map.purge_expired(now).collect();
+                    let mut expected: Vec<(u8, u8)> = model.iter()
+                        .filter(|(_, (_, deadline))| *deadline <= now)
+                        .map(|(key, (value, _))| (*key, *value))
+                        .collect();
+                    model.retain(|_, (_, deadline)| *deadline > now);
+                    expired.sort();
+                    expected.sort();
+                    assert_eq!(expired, expected);
+                }
+                1 => map.clock().advance(Duration::from_secs(value as u64 % 8)),
+                _ => {
+                    let ttl = Duration::from_secs(ttl as u64 % 16);
+                    map.insert_with_ttl(key, value, ttl);
+                    model.insert(key, (value, now + ttl));
+                }
+            }
+            let now = map.clock().now();
+            for key in 0..=u8::MAX {
+                let live = model.get(&key).filter(|(_, deadline)| now < *deadline).map(|(value, _)| value);
+                assert_eq!(map.get(&key), live);
+            }
+            assert_eq!(map.len(), model.len());
+        }
+    }
+}
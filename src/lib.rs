@@ -1,14 +1,89 @@
+// tests always get std, for the collections they use as models
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+// the crate spells out `return` even at the end of a function
+#![allow(clippy::needless_return)]
+
+extern crate alloc;
+
+// trees are generic over its `Allocator` trait, which works on stable unlike `core::alloc`'s
+pub use allocator_api2;
+
 #[cfg(test)]
 extern crate quickcheck;
 #[cfg(test)]
 #[macro_use(quickcheck)]
 extern crate quickcheck_macros;
 
+mod error;
+pub use error::{Error, Result};
+
+mod compare;
+pub use compare::{Compare, Natural};
+
 mod node;
-pub use node::Node;
+pub use node::{Node, Rotation};
+
+#[cfg(feature = "stats")]
+mod stats;
+#[cfg(feature = "stats")]
+pub use stats::Stats;
+
+#[cfg(feature = "merkle")]
+mod merkle;
+
+mod observer;
+pub use observer::Observer;
+
+mod trace;
+pub use trace::{Step, Trace};
 
 mod tree;
 pub use tree::AVLTree;
 
+mod transaction;
+pub use transaction::Transaction;
+
+mod undo;
+pub use undo::UndoableTree;
+
+mod diff;
+pub use diff::{Change, Diff};
+
+#[cfg(feature = "rayon")]
+mod par;
+#[cfg(feature = "rayon")]
+pub use par::ParIter;
+
 mod iter;
-pub use iter::{NodeIter, BreadthIter};
+pub use iter::{IterType, NodeIter, BreadthIter, Range, IntoIter};
+
+mod walk;
+pub use walk::{Walk, Drain, Span};
+
+mod rbtree;
+pub use rbtree::{RBTree, RBIter, RBIntoIter, RBRange};
+
+mod splay;
+pub use splay::{SplayTree, SplayIter, SplayIntoIter, SplayRange};
+
+mod treap;
+pub use treap::{Treap, TreapIter, TreapIntoIter, TreapRange};
+
+mod set;
+pub use set::{AVLSet, SetIter, SetRange, Union, Intersection, Difference, SymmetricDifference};
+
+mod multimap;
+pub use multimap::{AVLMultiMap, MultiRange};
+
+mod codec;
+pub use codec::Codec;
+
+#[cfg(feature = "std")]
+mod durable;
+#[cfg(feature = "std")]
+pub use durable::{DurableAVLTree, DurableOptions, SyncPolicy};
+
+mod expiring;
+pub use expiring::{Clock, ExpiringMap, ManualClock};
+#[cfg(feature = "std")]
+pub use expiring::SystemClock;
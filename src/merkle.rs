@@ -0,0 +1,272 @@
+use crate::{AVLTree, Compare, Node};
+use allocator_api2::alloc::Allocator;
+use core::cmp::Ordering::{Greater, Less};
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::ops::{Bound, RangeBounds};
+use core::sync::atomic::{AtomicU64, Ordering::{Acquire, Relaxed, Release}};
+use alloc::vec;
+use alloc::vec::Vec;
+
+/* a digest is a polynomial hash of the in-order sequence of key/data pairs, modulo the
+ * Mersenne prime 2^61 - 1. the digest of a concatenation can be computed from the digests
+ * of its parts, so each node's digest is built from its children's, and two trees holding
+ * the same pairs hash the same whatever their shape
+ */
+const P: u64 = (1 << 61) - 1;
+const BASE: u64 = 0x0a3d_70a3_d70a_3d71 % P;
+
+fn mul(a: u64, b: u64) -> u64 {
+    return ((a as u128 * b as u128) % P as u128) as u64;
+}
+
+/// the splitmix64 finalizer
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    return x ^ (x >> 31);
+}
+
+/// FNV-1a, so that digests don't depend on per-process hasher keys and can be compared
+/// between processes
+struct Fnv(u64);
+
+impl Hasher for Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        return self.0;
+    }
+}
+
+/// the digest of a sequence: its polynomial hash, and `BASE` raised to its length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Summary {
+    hash: u64,
+    pow: u64,
+}
+
+impl Summary {
+    const EMPTY: Summary = Summary { hash: 0, pow: 1 };
+
+    fn item<K: Hash, D: Hash>(key: &K, data: &D) -> Self {
+        let mut hasher = Fnv(0xcbf2_9ce4_8422_2325);
+        key.hash(&mut hasher);
+        data.hash(&mut hasher);
+        return Summary { hash: mix(hasher.finish()) % P, pow: BASE };
+    }
+
+    /// the digest of this sequence followed by `next`
+    fn then(self, next: Summary) -> Self {
+        return Summary { hash: (mul(self.hash, next.pow) + next.hash) % P, pow: mul(self.pow, next.pow) };
+    }
+
+    fn finish(self) -> u64 {
+        return mix(self.hash ^ self.pow.rotate_left(32));
+    }
+}
+
+/// a node's cached summary. atomics let digests be filled in through `&self` without
+/// making trees `!Sync`. `pow` is never zero in a real summary, so zero marks it stale
+#[derive(Debug, Default)]
+pub(crate) struct Digest {
+    hash: AtomicU64,
+    pow: AtomicU64,
+}
+
+impl Digest {
+    pub(crate) fn clear(&mut self) {
+        *self.pow.get_mut() = 0;
+    }
+
+    fn get(&self) -> Option<Summary> {
+        // pairs with the release in `set`, so a current `pow` is never read with an old `hash`
+        let pow = self.pow.load(Acquire);
+        if pow == 0 { return None }
+        return Some(Summary { hash: self.hash.load(Relaxed), pow });
+    }
+
+    fn set(&self, summary: Summary) {
+        self.hash.store(summary.hash, Relaxed);
+        self.pow.store(summary.pow, Release);
+    }
+}
+
+impl Clone for Digest {
+    fn clone(&self) -> Self {
+        let digest = Digest::default();
+        if let Some(summary) = self.get() { digest.set(summary) }
+        return digest;
+    }
+}
+
+impl<K: Hash, D: Hash, A: Allocator> Node<K,D,A> {
+    /// the summary of this subtree, filling in any stale digests below it first. only
+    /// subtrees changed since they were last hashed are visited
+    fn summary(&self) -> Summary {
+        if let Some(summary) = self.digest.get() { return summary }
+        let mut stack = vec![(self, false)];
+        while let Some((node, children_done)) = stack.pop() {
+            if children_done {
+                let summary = Self::cached(node.left.as_deref())
+                    .then(Summary::item(&node.key, &node.data))
+                    .then(Self::cached(node.right.as_deref()));
+                node.digest.set(summary);
+            } else {
+                stack.push((node, true));
+                for child in [node.left.as_deref(), node.right.as_deref()].iter().flatten() {
+                    if child.digest.get().is_none() { stack.push((child, false)) }
+                }
+            }
+        }
+        return self.digest.get().expect("digest not filled in");
+    }
+
+    fn cached(node: Option<&Self>) -> Summary {
+        return node.map_or(Summary::EMPTY, |node| node.digest.get().expect("stale child digest"));
+    }
+}
+
+impl<K, D, A: Allocator> Node<K,D,A> {
+    /// whether the cached digests of two subtrees say they hold the same pairs. false if
+    /// either digest is stale
+    pub(crate) fn same_digest(&self, other: &Self) -> bool {
+        return matches!((self.digest.get(), other.digest.get()), (Some(a), Some(b)) if a == b);
+    }
+}
+
+impl<K: Hash, D: Hash, C, A: Allocator> AVLTree<K,D,C,A> {
+    /// a hash of the tree's key/data pairs in order. trees with the same contents have the
+    /// same hash whatever their shape, so this is a quick inequality test, and an equality
+    /// test up to the odds of a collision. costs O(1) when nothing has changed since the
+    /// last call, and O(log n) per change otherwise. hashes are stable between processes
+    /// on the same platform, but `Hash` implementations may write platform-sized integers.
+    /// data changed in place through `root` or `Node::get_mut` leaves the cache stale
+    pub fn root_hash(&self) -> u64 {
+        return self.root.as_deref().map_or(Summary::EMPTY, Node::summary).finish();
+    }
+}
+
+impl<K, D, C, A> AVLTree<K,D,C,A>
+where K: Hash + Clone + fmt::Debug, D: Hash + Clone + fmt::Debug, C: Compare<K>, A: Allocator + Clone
+{
+    /// the hash that `root_hash` would give a tree holding only the pairs whose keys fall
+    /// within `range`, in O(log n) once digests are current. two replicas can find where
+    /// they differ by comparing hashes of ever smaller ranges
+    pub fn range_hash<R: RangeBounds<K>>(&self, range: R) -> u64 {
+        enum Step<'a, K, D, A: Allocator> {
+            // a subtree, and whether its keys are already known to be above the start of
+            // the range and below its end
+            Enter(&'a Node<K,D,A>, bool, bool),
+            Item(&'a Node<K,D,A>),
+        }
+
+        let cmp = self.comparator();
+        let below = |key: &K| match range.start_bound() {
+            Bound::Included(start) => cmp.compare(key, start) == Less,
+            Bound::Excluded(start) => cmp.compare(key, start) != Greater,
+            Bound::Unbounded => false
+        };
+        let above = |key: &K| match range.end_bound() {
+            Bound::Included(end) => cmp.compare(key, end) == Greater,
+            Bound::Excluded(end) => cmp.compare(key, end) != Less,
+            Bound::Unbounded => false
+        };
+        let lower_ok = matches!(range.start_bound(), Bound::Unbounded);
+        let upper_ok = matches!(range.end_bound(), Bound::Unbounded);
+
+        let mut summary = Summary::EMPTY;
+        let mut stack: Vec<Step<K,D,A>> = self.root.as_deref()
+            .map(|root| Step::Enter(root, lower_ok, upper_ok))
+            .into_iter().collect();
+        while let Some(step) = stack.pop() {
+            match step {
+                Step::Item(node) => summary = summary.then(Summary::item(&node.key, &node.data)),
+                Step::Enter(node, true, true) => summary = summary.then(node.summary()),
+                Step::Enter(node, lower_ok, upper_ok) => {
+                    let (left, right) = (node.left.as_deref(), node.right.as_deref());
+                    if !lower_ok && below(&node.key) {
+                        stack.extend(right.map(|right| Step::Enter(right, lower_ok, upper_ok)));
+                    } else if !upper_ok && above(&node.key) {
+                        stack.extend(left.map(|left| Step::Enter(left, lower_ok, upper_ok)));
+                    } else {
+                        // left subtree first, so push it last
+                        stack.extend(right.map(|right| Step::Enter(right, true, upper_ok)));
+                        stack.push(Step::Item(node));
+                        stack.extend(left.map(|left| Step::Enter(left, lower_ok, true)));
+                    }
+                }
+            }
+        }
+        return summary.finish();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[quickcheck]
+    fn qc_test_shape_independent(xs: Vec<(u16, u8)>) {
+        let mut tree = AVLTree::new();
+        for (key, data) in xs.iter() { tree.put(*key, *data); }
+        let map: BTreeMap<u16, u8> = xs.into_iter().collect();
+        // built in one pass, so almost certainly a different shape
+        let rebuilt = AVLTree::from_sorted_iter(map);
+        assert_eq!(tree.root_hash(), rebuilt.root_hash());
+        assert_eq!(tree.clone().root_hash(), tree.root_hash());
+    }
+
+    /// cached digests must stay current through every path that changes the tree
+    #[quickcheck]
+    fn qc_test_mutations(xs: BTreeMap<u8, u8>, ops: Vec<(u8, u8, u8)>) {
+        let mut tree = AVLTree::from(xs);
+        tree.root_hash();
+        for (op, key, data) in ops {
+            match op % 5 {
+                0 => { tree.put(key, data); }
+                1 => { let _ = tree.del(key); }
+                2 => { tree.ins(Node::newbox(key, data)); }
+                3 => { tree.ins_with(Node::newbox(key, data), |old, new| *old ^= new); }
+                _ => tree.retain(|k, d| { *d = d.wrapping_add(1); *k % 7 != key % 7 })
+            }
+            assert_eq!(tree.root_hash(), AVLTree::from_sorted_iter(tree.items()).root_hash());
+        }
+    }
+
+    #[quickcheck]
+    fn qc_test_range_hash(xs: BTreeMap<u8, u8>, a: u8, b: u8) {
+        let tree = AVLTree::from(xs.clone());
+        let expected = |range: (Bound<u8>, Bound<u8>)| {
+            AVLTree::from_sorted_iter(xs.range(range).map(|(key, data)| (*key, *data))).root_hash()
+        };
+        let (lo, hi) = (a.min(b), a.max(b));
+        assert_eq!(tree.range_hash(lo..hi), expected((Bound::Included(lo), Bound::Excluded(hi))));
+        assert_eq!(tree.range_hash(lo..=hi), expected((Bound::Included(lo), Bound::Included(hi))));
+        assert_eq!(tree.range_hash((Bound::Excluded(lo), Bound::Unbounded)), expected((Bound::Excluded(lo), Bound::Unbounded)));
+        assert_eq!(tree.range_hash(..hi), expected((Bound::Unbounded, Bound::Excluded(hi))));
+        assert_eq!(tree.range_hash(..), tree.root_hash());
+    }
+
+    #[test]
+    fn test_bisect() {
+        let a = AVLTree::from_sorted_iter((0..1000u32).map(|key| (key, key)));
+        let mut b = a.clone();
+        b.put(617, 0);
+        assert_ne!(a.root_hash(), b.root_hash());
+
+        // narrow down the one differing key by halving the range each time
+        let (mut lo, mut hi) = (0, 1000);
+        while hi - lo > 1 {
+            let mid = (lo + hi) / 2;
+            if a.range_hash(lo..mid) != b.range_hash(lo..mid) { hi = mid } else { lo = mid }
+        }
+        assert_eq!(lo, 617);
+    }
+}
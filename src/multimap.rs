@@ -0,0 +1,165 @@
+use crate::{AVLTree, Node, Range};
+use core::fmt;
+use core::ops::RangeBounds;
+use core::iter;
+use alloc::collections::{vec_deque, VecDeque};
+use alloc::vec::Vec;
+use allocator_api2::boxed::Box;
+
+/// an ordered map that keeps every value put under a key, in insertion order
+pub struct AVLMultiMap<K, V> {
+    // a deque, so the oldest value can be taken off the front in O(1)
+    tree: AVLTree<K, VecDeque<V>>,
+    // number of values, not keys
+    len: usize
+}
+
+impl<K, V> AVLMultiMap<K, V>
+where K: Ord + Eq + Clone + fmt::Debug, V: Clone + fmt::Debug
+{
+    pub fn new() -> Self {
+        Self { tree: AVLTree::new(), len: 0 }
+    }
+
+    /// add a value under a key, after any values already stored there
+    pub fn insert(&mut self, key: K, value: V) {
+        let node = Node::newbox(key, iter::once(value).collect());
+        self.tree.ins_with(node, |values, mut new| values.append(&mut new));
+        self.len += 1;
+    }
+
+    /// iterate over all values stored under a key, oldest first
+    pub fn get_all(&self, key: &K) -> vec_deque::Iter<'_, V> {
+        return match self.tree.find(key) {
+            Some(node) => node.data.iter(),
+            None => Default::default()
+        };
+    }
+
+    /// remove and return the oldest value stored under a key
+    pub fn remove_one(&mut self, key: &K) -> Option<V> {
+        let node = self.tree.find_mut(key)?;
+        let value = node.data.pop_front()?;
+        if node.data.is_empty() {
+            self.remove_all(key);
+        }
+        self.len -= 1;
+        return Some(value);
+    }
+
+    /// remove and return every value stored under a key, oldest first
+    pub fn remove_all(&mut self, key: &K) -> Vec<V> {
+        let values = self.tree.remove_node(key).map_or_else(|_| Vec::new(), |node| Vec::from(Box::into_inner(node).into_pair().1));
+        self.len -= values.len();
+        return values;
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        return self.get_all(key).next().is_some();
+    }
+
+    /// number of values in the map, counting each value under a shared key
+    pub fn len(&self) -> usize {
+        return self.len;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len == 0;
+    }
+
+    /// iterate over every key/value pair, in key order and then insertion order
+    pub fn iter(&self) -> MultiRange<'_, K, V> {
+        return self.range(..);
+    }
+
+    /// iterate over the key/value pairs whose keys fall within `range`, in key order and
+    /// then insertion order
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> MultiRange<'_, K, V> {
+        return MultiRange { keys: self.tree.range(range), curr: None };
+    }
+}
+
+impl<K, V> Default for AVLMultiMap<K, V>
+where K: Ord + Eq + Clone + fmt::Debug, V: Clone + fmt::Debug
+{
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl<K, V> fmt::Debug for AVLMultiMap<K, V>
+where K: Ord + Eq + Clone + fmt::Debug, V: Clone + fmt::Debug
+{
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        return formatter.debug_map().entries(self.iter()).finish();
+    }
+}
+
+/// in-order iterator over the key/value pairs of an `AVLMultiMap`
+pub struct MultiRange<'a, K, V> {
+    keys: Range<'a, K, VecDeque<V>>,
+    curr: Option<(&'a K, vec_deque::Iter<'a, V>)>
+}
+
+impl<'a, K: Ord, V> Iterator for MultiRange<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((key, values)) = self.curr.as_mut() {
+                if let Some(value) = values.next() {
+                    return Some((key, value));
+                }
+            }
+            let (key, values) = self.keys.next()?;
+            self.curr = Some((key, values.iter()));
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[quickcheck]
+    fn qc_test_insert_remove(puts: Vec<(u8, isize)>, removes: Vec<(u8, bool)>) {
+        let mut map = AVLMultiMap::new();
+        let mut model: BTreeMap<u8, Vec<isize>> = BTreeMap::new();
+        for (key, value) in puts {
+            map.insert(key, value);
+            model.entry(key).or_default().push(value);
+        }
+
+        for (key, all) in removes {
+            if all {
+                assert_eq!(map.remove_all(&key), model.remove(&key).unwrap_or_default());
+            } else {
+                let values = model.entry(key).or_default();
+                let expected = if values.is_empty() { None } else { Some(values.remove(0)) };
+                if values.is_empty() { model.remove(&key); }
+                assert_eq!(map.remove_one(&key), expected);
+            }
+            assert!(map.get_all(&key).eq(model.get(&key).into_iter().flatten()));
+            assert_eq!(map.contains_key(&key), model.contains_key(&key));
+        }
+
+        assert_eq!(map.len(), model.values().map(|v| v.len()).sum::<usize>());
+        let pairs: Vec<(&u8, &isize)> = model.iter().flat_map(|(k, vs)| vs.iter().map(move |v| (k, v))).collect();
+        assert_eq!(map.iter().collect::<Vec<_>>(), pairs);
+    }
+
+    #[test]
+    fn test_range() {
+        let mut map = AVLMultiMap::new();
+        for (ts, event) in [(3, "c"), (1, "a"), (2, "b1"), (2, "b2"), (4, "d"), (2, "b3")].iter() {
+            map.insert(*ts, *event);
+        }
+        assert_eq!(map.len(), 6);
+        assert_eq!(map.get_all(&2).collect::<Vec<_>>(), vec![&"b1", &"b2", &"b3"]);
+        assert_eq!(
+            map.range(2..4).collect::<Vec<_>>(),
+            vec![(&2, &"b1"), (&2, &"b2"), (&2, &"b3"), (&3, &"c")]);
+    }
+}
@@ -1,26 +1,84 @@
-use std::cmp;
+use core::cmp;
 use cmp::Ordering::{Equal,Greater,Less};
-use std::fmt;
-
-use crate::{NodeIter, BreadthIter};
+use core::fmt;
+use core::borrow::Borrow;
+use core::{mem, ptr};
+use allocator_api2::alloc::{Allocator, Global};
+use allocator_api2::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::{format, vec};
+
+use crate::{BreadthIter, Compare, Error, Natural};
+#[cfg(feature = "merkle")]
+use crate::merkle::Digest;
 
 #[allow(unused_imports)]
 use log::{error, warn, info, debug, trace};
 
-type OptBoxNode<K,D> = Option<Box<Node<K,D>>>;
+pub(crate) type OptBoxNode<K,D,A> = Option<Box<Node<K,D,A>, A>>;
+
+/// what is left of a subtree after a delete, and the node that was taken out of it
+type Deleted<K,D,A> = (OptBoxNode<K,D,A>, Result<Box<Node<K,D,A>, A>, Error>);
+
+/// the keys before a split point, the node at it if any, and the keys after it
+#[cfg(feature = "rayon")]
+pub(crate) type Split<K,D,A> = (OptBoxNode<K,D,A>, OptBoxNode<K,D,A>, OptBoxNode<K,D,A>);
+
+/// the rotation applied to restore balance at a node
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rotation {
+    Left,
+    Right,
+    LeftRight,
+    RightLeft,
+}
+
+/// receives events from inside the node algorithms as they change a subtree. every method
+/// does nothing by default
+pub(crate) trait Listener<K, D> {
+    /// a node for a new key was linked in, before the path above it is rebalanced
+    fn inserted(&self, _key: &K, _data: &D) {}
+    /// a node took the place of the node that held the same key
+    fn replaced(&self, _key: &K, _old: &D, _new: &D) {}
+    /// a node is about to be unlinked
+    fn removed(&self, _key: &K, _data: &D) {}
+    /// `rebalance` was called on a node
+    fn rebalanced(&self) {}
+    /// `pivot` was rotated down and `promoted` took its place. `before` is the pivot's
+    /// balance factor beforehand and `after` the promoted node's once the rotation is done
+    fn rotated(&self, _rotation: Rotation, _pivot: &K, _promoted: &K, _before: isize, _after: isize) {}
+}
+
+/// a bare comparator, for callers with no one to tell about rotations
+pub(crate) struct Quiet<'a, C>(pub(crate) &'a C);
+
+impl<Q: ?Sized, C: Compare<Q>> Compare<Q> for Quiet<'_, C> {
+    fn compare(&self, a: &Q, b: &Q) -> cmp::Ordering {
+        return self.0.compare(a, b);
+    }
+}
+
+impl<K, D, C> Listener<K,D> for Quiet<'_, C> {}
 
 #[derive(Default)]
-pub struct Node<K, D> {
+/// a node of an AVL tree, allocated by `A`
+pub struct Node<K, D, A: Allocator = Global> {
     pub key: K,
     pub data: D,
 
     pub height: usize,
 
-    pub left: OptBoxNode<K,D>,
-    pub right: OptBoxNode<K,D>,
+    pub left: OptBoxNode<K,D,A>,
+    pub right: OptBoxNode<K,D,A>,
+
+    /// hash of the subtree's contents, filled in lazily and cleared whenever the subtree
+    /// changes
+    #[cfg(feature = "merkle")]
+    pub(crate) digest: Digest,
 }
 
-impl<K: fmt::Debug, D: fmt::Debug> fmt::Debug for Node<K,D> {
+impl<K: fmt::Debug, D: fmt::Debug, A: Allocator> fmt::Debug for Node<K,D,A> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         // TODO: implement f.alternate() to pretty print
         let left = match &self.left {
@@ -35,7 +93,7 @@ impl<K: fmt::Debug, D: fmt::Debug> fmt::Debug for Node<K,D> {
     }
 }
 
-impl<K: fmt::Debug + fmt::Display, D: fmt::Debug + fmt::Display> fmt::Display for Node<K,D> {
+impl<K: fmt::Debug + fmt::Display, D: fmt::Debug + fmt::Display, A: Allocator> fmt::Display for Node<K,D,A> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let left = match &self.left {
             Some(node) => format!("Node {{ {}:{} }}", node.key, node.data),
@@ -49,103 +107,247 @@ impl<K: fmt::Debug + fmt::Display, D: fmt::Debug + fmt::Display> fmt::Display fo
     }
 }
 
-impl<K: fmt::Display + fmt::Debug + Eq + Ord, D: fmt::Display + fmt::Debug> Node<K,D>  {
+/// a chain of boxed nodes can be far deeper than the stack allows recursive drops, so the
+/// children are detached onto a heap stack and dropped one at a time
+impl<K, D, A: Allocator> Drop for Node<K,D,A> {
+    fn drop(&mut self) {
+        let mut stack: Vec<Box<Self, A>> = self.left.take().into_iter().chain(self.right.take()).collect();
+        while let Some(mut node) = stack.pop() {
+            stack.extend(node.left.take());
+            stack.extend(node.right.take());
+        }
+    }
+}
+
+/// copies the subtree bottom-up with an explicit stack, keeping its shape, so cloning a deep
+/// subtree doesn't recurse either
+impl<K: Clone, D: Clone, A: Allocator + Clone> Clone for Node<K,D,A> {
+    fn clone(&self) -> Self {
+        // each node is pushed again once its children are queued, and copied on that second
+        // visit, by which time copies of its subtrees are at the top of `done`
+        let mut stack = vec![(self, false)];
+        let mut done: Vec<Self> = Vec::new();
+        while let Some((node, expanded)) = stack.pop() {
+            if !expanded {
+                stack.push((node, true));
+                stack.extend(node.right.as_deref().map(|right| (right, false)));
+                stack.extend(node.left.as_deref().map(|left| (left, false)));
+                continue;
+            }
+            let right = node.right.as_ref().map(|right| Box::new_in(done.pop().expect("right subtree not copied"), Box::allocator(right).clone()));
+            let left = node.left.as_ref().map(|left| Box::new_in(done.pop().expect("left subtree not copied"), Box::allocator(left).clone()));
+            done.push(Self {
+                key: node.key.clone(),
+                data: node.data.clone(),
+                height: node.height,
+                left, right,
+                #[cfg(feature = "merkle")]
+                digest: node.digest.clone(),
+            });
+        }
+        return done.pop().expect("root not copied");
+    }
+}
+
+impl<K, D, A: Allocator> Node<K,D,A> {
+    /// take the node apart into its key and data, dropping its subtrees. `Node` implements
+    /// `Drop`, so its fields can't be moved out directly
+    pub fn into_pair(mut self) -> (K, D) {
+        drop((self.left.take(), self.right.take()));
+        let node = mem::ManuallyDrop::new(self);
+        // SAFETY: `node` is never used or dropped again, so the key and data are each read
+        // out exactly once. its children were dropped above and a digest owns no memory, so
+        // skipping the rest of its drop leaks nothing
+        return unsafe { (ptr::read(&node.key), ptr::read(&node.data)) };
+    }
+}
+
+/// constructors for nodes from the global allocator
+impl<K: fmt::Debug, D: fmt::Debug> Node<K,D> {
     pub fn new(key: K, data: D) -> Self {
-        return Self { key, data, height: 0, left: None, right: None };
+        return Self::leaf(key, data);
     }
 
     pub fn newbox(key: K, data: D) -> Box<Self> {
-        return Box::new(Self::new(key, data));
+        return Self::newbox_in(key, data, Global);
+    }
+
+    /// build a perfectly balanced subtree from the next `count` items of an iterator that
+    /// yields keys in strictly increasing order. runs in O(count)
+    pub fn from_sorted<I: Iterator<Item = (K,D)>>(iter: &mut I, count: usize) -> OptBoxNode<K,D,Global> {
+        return Self::from_sorted_in(iter, count, &Global);
+    }
+}
+
+impl<K: fmt::Debug, D: fmt::Debug, A: Allocator + Clone> Node<K,D,A> {
+    fn leaf(key: K, data: D) -> Self {
+        return Self {
+            key, data, height: 1, left: None, right: None,
+            #[cfg(feature = "merkle")]
+            digest: Digest::default(),
+        };
+    }
+
+    /// allocate a new node with `alloc`
+    pub fn newbox_in(key: K, data: D, alloc: A) -> Box<Self, A> {
+        return Box::new_in(Self::leaf(key, data), alloc);
     }
 
-    pub fn iter_breadth<'a>(self: &'a Box<Self>) -> BreadthIter<'a,K,D> {
+    pub fn iter_breadth(&self) -> BreadthIter<'_,K,D,A> {
         return BreadthIter::with_root(self);
     }
 
     /*
     /// iterate left, middle, right
-    pub fn iter_inorder<'a>(self: &'a Box<Self>) -> NodeIter<'a, K, D> {
+    pub fn iter_inorder<'a>(self: &'a Box<Self, A>) -> NodeIter<'a, K, D> {
         
     }
     /// iterate right, middle, left
-    pub fn iter_inorder_reverse<'a>(self: &'a Box<Self>) -> NodeIter<'a, K, D> {
+    pub fn iter_inorder_reverse<'a>(self: &'a Box<Self, A>) -> NodeIter<'a, K, D> {
         
     }
     /// iterate middle, left, right
-    pub fn iter_preorder<'a>(self: &'a Box<Self>) -> NodeIter<'a, K, D> {
+    pub fn iter_preorder<'a>(self: &'a Box<Self, A>) -> NodeIter<'a, K, D> {
         
     }
     /// iterate left, right, middle
-    pub fn iter_postorder<'a>(self: &'a Box<Self>) -> NodeIter<'a, K, D> {
+    pub fn iter_postorder<'a>(self: &'a Box<Self, A>) -> NodeIter<'a, K, D> {
         
     }
     */
 
-    pub fn height(&mut self) -> usize {
-        // cache result from potentially expensive drill-down
-        // TODO: when does this need to be invalidated?
-        /*
-        if self.height != 0 {
-            return self.height;
-        }
-        */
-
-        return self.update_height();
+    /// height of the subtree rooted at this node, counting the node itself
+    /// heights are cached in each node and kept current by inserts, deletes and rotations
+    pub fn height(&self) -> usize {
+        return self.height;
     }
 
+    /// recompute this node's height from its children's cached heights
     fn update_height(&mut self) -> usize {
-        self.height = (cmp::max(self.left_height(), self.right_height()) + 1) as usize;
+        self.touch();
+        self.height = cmp::max(self.left_height(), self.right_height()) + 1;
         return self.height;
     }
 
+    /// note that this node's children or data changed, so anything cached about its
+    /// subtree's contents is stale
+    fn touch(&mut self) {
+        #[cfg(feature = "merkle")]
+        self.digest.clear();
+    }
+
+    /// recompute the cached height of every node in a subtree, bottom-up. needed for trees
+    /// assembled by hand, whose heights can't be trusted
+    pub fn recompute_heights(root: Box<Self, A>) -> Box<Self, A> {
+        enum Step<K, D, A: Allocator> {
+            Enter(Box<Node<K,D,A>, A>),
+            // children have been detached and pushed; reattach them on the way back up
+            Exit(Box<Node<K,D,A>, A>, bool, bool),
+        }
+
+        let mut stack = vec![Step::Enter(root)];
+        let mut done: Vec<Box<Self, A>> = Vec::new();
+        while let Some(step) = stack.pop() {
+            match step {
+                Step::Enter(mut node) => {
+                    let (left, right) = (node.left.take(), node.right.take());
+                    stack.push(Step::Exit(node, left.is_some(), right.is_some()));
+                    stack.extend(right.map(Step::Enter));
+                    stack.extend(left.map(Step::Enter));
+                }
+                Step::Exit(mut node, has_left, has_right) => {
+                    // the left subtree finished first, so the right one is on top
+                    if has_right { node.right = done.pop() }
+                    if has_left { node.left = done.pop() }
+                    node.update_height();
+                    done.push(node);
+                }
+            }
+        }
+        return done.pop().expect("no root");
+    }
+
     /// return the difference in height between the right tree and the left tree
     /// a positive value indicates that the right tree is deeper
     /// a negative value indicates that the left tree is deeper
-    pub fn balance_factor(&mut self) -> isize {
+    pub fn balance_factor(&self) -> isize {
         return self.right_height() as isize - self.left_height() as isize;
     }
-    pub fn left_heavy(&mut self) -> bool {
+    pub fn left_heavy(&self) -> bool {
         self.balance_factor() < 0
     }
-    pub fn right_heavy(&mut self) -> bool {
+    pub fn right_heavy(&self) -> bool {
         self.balance_factor() > 0
     }
-    fn right_height(&mut self) -> usize {
-        return match &mut self.right {
-            Some(node) => node.height(),
-            None => 0
-        };
+    fn right_height(&self) -> usize {
+        return self.right.as_ref().map_or(0, |node| node.height);
     }
-    fn left_height(&mut self) -> usize {
-        return match &mut self.left {
-            Some(node) => node.height(),
-            None => 0
-        };
+    fn left_height(&self) -> usize {
+        return self.left.as_ref().map_or(0, |node| node.height);
     }
 
-    /// recursively search for the given key
-    pub fn get(&self, key: K) -> Option<&Node<K,D>> {
-        debug!("searching for key '{}'", key);
-        if key == self.key {
-            return Some(&self);
-        } else if key < self.key {
-            if let Some(node) = &self.left {
-                return node.get(key);
-            } else { 
-                return None; 
-            }
-        } else { // key > self.key
-            if let Some(node) = &self.right {
-                return node.get(key);
-            } else { 
-                return None; 
-            }
+    /// search for the given key, ordering keys with `cmp`
+    pub fn get_by<Q, C>(&self, key: &Q, cmp: &C) -> Option<&Node<K,D,A>>
+    where K: Borrow<Q>, Q: ?Sized, C: Compare<Q>
+    {
+        let mut node = self;
+        loop {
+            debug!("searching for key at {:?}", node.key);
+            node = match cmp.compare(key, node.key.borrow()) {
+                Equal => return Some(node),
+                Less => node.left.as_ref()?,
+                Greater => node.right.as_ref()?
+            };
         }
     }
 
+    /// search for the given key, returning a mutable reference to its node
+    /// the node's key must not be changed in a way that alters its order
+    pub fn get_mut_by<Q, C>(&mut self, key: &Q, cmp: &C) -> Option<&mut Node<K,D,A>>
+    where K: Borrow<Q>, Q: ?Sized, C: Compare<Q>
+    {
+        let mut node = self;
+        loop {
+            node = match cmp.compare(key, node.key.borrow()) {
+                Equal => return Some(node),
+                Less => node.left.as_mut()?,
+                Greater => node.right.as_mut()?
+            };
+        }
+    }
+
+    /// `from_sorted`, allocating the nodes with `alloc`
+    pub fn from_sorted_in<I: Iterator<Item = (K,D)>>(iter: &mut I, count: usize, alloc: &A) -> OptBoxNode<K,D,A> {
+        return Self::from_sorted_nodes(&mut iter.map(|(key, data)| Self::newbox_in(key, data, alloc.clone())), count);
+    }
+
+    /// like `from_sorted`, but relinks already-allocated nodes instead of allocating new ones
+    /// any children the nodes have are discarded
+    pub fn from_sorted_nodes<I: Iterator<Item = Box<Self, A>>>(iter: &mut I, count: usize) -> OptBoxNode<K,D,A> {
+        if count == 0 { return None }
+
+        // fill the left subtree first so that nodes are consumed in key order
+        let left = Self::from_sorted_nodes(iter, count / 2);
+        let mut node = iter.next()?;
+        let right = Self::from_sorted_nodes(iter, count - count / 2 - 1);
+
+        node.link(left, right);
+        return Some(node);
+    }
+
+    /// make `left` and `right` this node's children, whose cached heights must be current
+    pub(crate) fn link(&mut self, left: OptBoxNode<K,D,A>, right: OptBoxNode<K,D,A>) {
+        self.height = cmp::max(
+            left.as_ref().map_or(0, |node| node.height),
+            right.as_ref().map_or(0, |node| node.height)) + 1;
+        self.left = left;
+        self.right = right;
+        self.touch();
+    }
+
     /*
     /// insert a new key/data pair
-    pub fn put(mut self: Box<Self>, key: K, data: D) -> Box<Self> {
+    pub fn put(mut self: Box<Self, A>, key: K, data: D) -> Box<Self, A> {
         self.height = 0;
         trace!("put {}:{} into self: {}", key, data, &*self);
 
@@ -170,7 +372,7 @@ impl<K: fmt::Display + fmt::Debug + Eq + Ord, D: fmt::Display + fmt::Debug> Node
         return self.rebalance();
     }
 
-    fn put_in_child(&mut self, key: K, data: D, child: OptBoxNode<K,D>) -> OptBoxNode<K,D> {
+    fn put_in_child(&mut self, key: K, data: D, child: OptBoxNode<K,D,A>) -> OptBoxNode<K,D,A> {
         Some(
             match child {
                 Some(node) => node.put(key, data),
@@ -182,34 +384,107 @@ impl<K: fmt::Display + fmt::Debug + Eq + Ord, D: fmt::Display + fmt::Debug> Node
         )
     }
     */
-    /// insert a new key/data pair
-    pub fn put(self: Box<Self>, key: K, data: D) -> Box<Self> {
-        let node = Node::newbox(key, data);
-        return self.ins(node);
+    /// insert a new key/data pair, ordering keys with `cmp`
+    /// returns the new subtree root and the node that held the key before, if any
+    /// the new node is allocated by the root's allocator
+    pub fn put_by<C: Compare<K>>(root: Box<Self, A>, key: K, data: D, cmp: &C) -> (Box<Self, A>, OptBoxNode<K,D,A>) {
+        let node = Node::newbox_in(key, data, Box::allocator(&root).clone());
+        return Self::ins_by(root, node, cmp);
     }
 
 
-    /// insert an already-allocated node
-    pub fn ins(mut self: Box<Self>, other: Box<Node<K,D>>) -> Box<Self> {
-        match self.key.cmp(&other.key) {
-            Equal => return other,
-            Greater => {
-                let l = self.left.take();
-                self.left = self.ins_in_child(other, l)
+    /// insert an already-allocated node, ordering keys with `cmp`
+    /// if the key is already present, `other` takes its place and the old node is returned
+    /// detached from the tree
+    pub fn ins_by<C: Compare<K>>(root: Box<Self, A>, other: Box<Node<K,D,A>, A>, cmp: &C) -> (Box<Self, A>, OptBoxNode<K,D,A>) {
+        return Self::ins_in(root, other, &Quiet(cmp));
+    }
+
+    /// `ins_by`, reporting each change to `ctx`
+    pub(crate) fn ins_in<X>(root: Box<Self, A>, mut other: Box<Node<K,D,A>, A>, ctx: &X) -> (Box<Self, A>, OptBoxNode<K,D,A>)
+    where X: Compare<K> + Listener<K,D>
+    {
+        let mut path = Vec::new();
+        let mut node = root;
+        loop {
+            let dir = ctx.compare(&other.key, &node.key);
+            let child = match dir {
+                Equal => {
+                    other.left = node.left.take();
+                    other.right = node.right.take();
+                    other.height = node.height;
+                    other.touch();
+                    ctx.replaced(&other.key, &node.data, &other.data);
+                    // replacing a node doesn't change the shape of the tree
+                    let root = Self::reattach(path, Some(other), ctx, false).expect("no root");
+                    return (root, Some(node));
+                }
+                Less => node.left.take(),
+                Greater => node.right.take()
+            };
+            path.push((node, dir));
+            match child {
+                Some(child) => node = child,
+                None => {
+                    ctx.inserted(&other.key, &other.data);
+                    return (Self::reattach(path, Some(other), ctx, true).expect("no root"), None);
+                }
             }
-            Less => {
-                let r = self.right.take();
-                self.right = self.ins_in_child(other, r)
+        }
+    }
+
+    /// insert an already-allocated node, ordering keys with `cmp`. if the key is already
+    /// present, `merge` folds the new node's data into the existing node's data instead of
+    /// replacing it
+    /// returns the new subtree root and whether the key was new
+    pub fn ins_with_by<C, F>(root: Box<Self, A>, other: Box<Node<K,D,A>, A>, cmp: &C, merge: F) -> (Box<Self, A>, bool)
+    where C: Compare<K>, F: FnOnce(&mut D, D)
+    {
+        return Self::ins_with_in(root, other, &Quiet(cmp), merge);
+    }
+
+    /// `ins_with_by`, reporting each change to `ctx`. data merged into an existing node is not
+    /// reported
+    pub(crate) fn ins_with_in<X, F>(root: Box<Self, A>, other: Box<Node<K,D,A>, A>, ctx: &X, merge: F) -> (Box<Self, A>, bool)
+    where X: Compare<K> + Listener<K,D>, F: FnOnce(&mut D, D)
+    {
+        let mut path = Vec::new();
+        let mut node = root;
+        loop {
+            let dir = ctx.compare(&other.key, &node.key);
+            let child = match dir {
+                Equal => {
+                    merge(&mut node.data, Box::into_inner(other).into_pair().1);
+                    node.touch();
+                    return (Self::reattach(path, Some(node), ctx, false).expect("no root"), false);
+                }
+                Less => node.left.take(),
+                Greater => node.right.take()
+            };
+            path.push((node, dir));
+            match child {
+                Some(child) => node = child,
+                None => {
+                    ctx.inserted(&other.key, &other.data);
+                    return (Self::reattach(path, Some(other), ctx, true).expect("no root"), true);
+                }
             }
         }
-        return self.rebalance();
     }
 
-    fn ins_in_child(&mut self, other: Box<Node<K,D>>, child: OptBoxNode<K,D>) -> OptBoxNode<K,D> {
-        return Some(match child {
-            Some(node) => node.ins(other),
-            None => other
-        })
+    /// relink a subtree to the ancestors that were detached on the way down to it, from the
+    /// bottom of `path` back up to the root. `path` holds each ancestor along with the side
+    /// the descent took. if `rebalance` is set, each ancestor is rebalanced as it's relinked
+    fn reattach<L: Listener<K,D>>(mut path: Vec<(Box<Self, A>, cmp::Ordering)>, mut subtree: OptBoxNode<K,D,A>, ctx: &L, rebalance: bool) -> OptBoxNode<K,D,A> {
+        while let Some((mut parent, dir)) = path.pop() {
+            match dir {
+                Less => parent.left = subtree,
+                _ => parent.right = subtree
+            }
+            parent.touch();
+            subtree = Some(if rebalance { Self::rebalance(parent, ctx) } else { parent });
+        }
+        return subtree;
     }
 
     /* right rotation after a node is inserted in the left subtree of a left subtree
@@ -219,39 +494,55 @@ impl<K: fmt::Display + fmt::Debug + Eq + Ord, D: fmt::Display + fmt::Debug> Node
      * ref: https://www.educative.io/edpresso/common-avl-rotation-techniques
      */
     /// check the balance factor of a subtree rooted at a node and apply any necessary rotations
-    fn rebalance(mut self: Box<Self>) -> Box<Node<K,D>> 
-    where K: Eq + Ord, {
-        let bf = self.balance_factor();
-        trace!("balance factor {} for {}", &bf, &self);
-        match bf {
+    fn rebalance<L: Listener<K,D>>(mut node: Box<Self, A>, ctx: &L) -> Box<Node<K,D,A>, A> {
+        ctx.rebalanced();
+        node.update_height();
+        let bf = node.balance_factor();
+        trace!("balance factor {} for {:?}", &bf, &node);
+        let rotation = match bf {
             -2 => {
                 // the sub-tree rooted at this node is left-heavy
-                let left: &mut Box<Node<K,D>> = self.left.as_mut().expect("no left node");
-                // if the left node is left-heavy, we have a simple rotation
-                if left.left_heavy() {
-                    trace!("left node is left heavy: left = {}", &left);
-                    return self.rotate_right();
+                let left: &Node<K,D,A> = node.left.as_deref().expect("no left node");
+                // if the left node is left-heavy (or, after a delete, balanced), we have a
+                // simple rotation
+                if !left.right_heavy() {
+                    trace!("left node is left heavy: left = {:?}", &left);
+                    Rotation::Right
                 } else {
                     // left node is right-heavy, do a left-right rotation
-                    trace!("left node is right heavy: left = {}", &left);
-                    return self.rotate_left_right();
+                    trace!("left node is right heavy: left = {:?}", &left);
+                    Rotation::LeftRight
                 }
             }
             2 => {
                 // the sub-tree rooted at this node is right-heavy
-                let right: &mut Box<Node<K,D>> = self.right.as_mut().expect("no right node");
-                // if the right node is right-heavy, we have a simple rotation
-                if right.right_heavy() {
-                    trace!("right node is right heavy: right = {}", &right);
-                    return self.rotate_left();
+                let right: &Node<K,D,A> = node.right.as_deref().expect("no right node");
+                // if the right node is right-heavy (or, after a delete, balanced), we have a
+                // simple rotation
+                if !right.left_heavy() {
+                    trace!("right node is right heavy: right = {:?}", &right);
+                    Rotation::Left
                 } else {
                     // right node is left-heavy, do a right-left rotation
-                    trace!("right node is left heavy: right = {}", &right);
-                    return self.rotate_right_left();
+                    trace!("right node is left heavy: right = {:?}", &right);
+                    Rotation::RightLeft
                 }
             }
-            _ => return self
+            _ => return node
+        };
+        let root = match rotation {
+            Rotation::Left => Self::rotate_left(node),
+            Rotation::Right => Self::rotate_right(node),
+            Rotation::LeftRight => Self::rotate_left_right(node),
+            Rotation::RightLeft => Self::rotate_right_left(node),
         };
+        // the old root always ends up on the side it was rotated toward
+        let pivot = match rotation {
+            Rotation::Right | Rotation::LeftRight => root.right.as_ref(),
+            Rotation::Left | Rotation::RightLeft => root.left.as_ref(),
+        }.expect("no pivot");
+        ctx.rotated(rotation, &pivot.key, &root.key, bf, root.balance_factor());
+        return root;
     }
 
 
@@ -265,14 +556,16 @@ impl<K: fmt::Display + fmt::Debug + Eq + Ord, D: fmt::Display + fmt::Debug> Node
      *
      */
      /// applied when a node is inserted in the left subtree of a left subtree
+    fn rotate_right(mut root: Box<Self, A>) -> Box<Self, A> {
+        trace!("rotate_right: {:?}", root);
+        let mut left: Box<Node<K,D,A>, A> = root.left.take().expect("no left child");
+        //let left_left: Box<Node<K,D,A>, A> = left.left.take().expect("no left-left child");
 
-    fn rotate_right(mut self: Box<Self>) -> Box<Self> {
-        trace!("rotate_right: {}", self);
-        let mut left: Box<Node<K,D>> = self.left.take().expect("no left child");
-        //let left_left: Box<Node<K,D>> = left.left.take().expect("no left-left child");
-
-        self.left = left.right;
-        left.right = Some(self);
+        root.left = left.right.take();
+        // root is now below left, so its height must be settled first
+        root.update_height();
+        left.right = Some(root);
+        left.update_height();
         return left;
     }
 
@@ -284,14 +577,16 @@ impl<K: fmt::Display + fmt::Debug + Eq + Ord, D: fmt::Display + fmt::Debug> Node
      * move root to root.right.left and return root.right
      */
      /// applied when a node is inserted in the right subtree of a right subtree
-    fn rotate_left(mut self: Box<Self>) -> Box<Self> {
-        trace!("rotate_left: {}", self);
-        let mut right: Box<Node<K,D>> = self.right.take().expect("no right child");
-        trace!("rotate_left: right_child: {}", &right);
-        //let right_right: Box<Node<K,D>> = right.right.take().expect("no right-right child");
-
-        self.right = right.left;
-        right.left = Some(self);
+    fn rotate_left(mut root: Box<Self, A>) -> Box<Self, A> {
+        trace!("rotate_left: {:?}", root);
+        let mut right: Box<Node<K,D,A>, A> = root.right.take().expect("no right child");
+        trace!("rotate_left: right_child: {:?}", &right);
+        //let right_right: Box<Node<K,D,A>, A> = right.right.take().expect("no right-right child");
+
+        root.right = right.left.take();
+        root.update_height();
+        right.left = Some(root);
+        right.update_height();
         return right;
     }
 
@@ -306,10 +601,10 @@ impl<K: fmt::Display + fmt::Debug + Eq + Ord, D: fmt::Display + fmt::Debug> Node
      * then right-rotate root
      */
      /// applied when a node is inserted in the right subtree of a left subtree
-    fn rotate_left_right(mut self: Box<Self>) -> Box<Self> {
-        trace!("rotate_left_right: {}", self);
-        self.left = Some(self.left.expect("no left child").rotate_left());
-        return self.rotate_right();
+    fn rotate_left_right(mut root: Box<Self, A>) -> Box<Self, A> {
+        trace!("rotate_left_right: {:?}", root);
+        root.left = Some(Self::rotate_left(root.left.take().expect("no left child")));
+        return Self::rotate_right(root);
     }
 
     /*
@@ -324,113 +619,275 @@ impl<K: fmt::Display + fmt::Debug + Eq + Ord, D: fmt::Display + fmt::Debug> Node
      *
      */
      /// applied when a node is inserted in the left subtree of a right subtree
-    fn rotate_right_left(mut self: Box<Self>) -> Box<Self> {
-        trace!("rotate_right_left: {}", self);
-        self.right = Some(self.right.expect("no right child").rotate_right());
-        return self.rotate_left();
+    fn rotate_right_left(mut root: Box<Self, A>) -> Box<Self, A> {
+        trace!("rotate_right_left: {:?}", root);
+        root.right = Some(Self::rotate_right(root.right.take().expect("no right child")));
+        return Self::rotate_left(root);
     }
 
     /*
     /// in a node with two children, in-order predecessor is right-most child of left subtree
-    fn in_order_pred(&self) -> &Box<Self> {
-        let mut node: &Box<Self> = self.left.as_ref().expect("no left child");
+    fn in_order_pred(&self) -> &Box<Self, A> {
+        let mut node: &Box<Self, A> = self.left.as_ref().expect("no left child");
         while let Some(next) = node.right.as_ref() { node = next };
         return node;
     }
 
     /// in a node with two children, in-order successor is left-most child of right subtree
-    fn in_order_succ(&self) -> &Box<Self> {
-        let mut node: &Box<Self> = self.right.as_ref().expect("no right child");
+    fn in_order_succ(&self) -> &Box<Self, A> {
+        let mut node: &Box<Self, A> = self.right.as_ref().expect("no right child");
         while let Some(next) = node.left.as_ref() { node = next };
         return node;
     }
     */
 
-    fn pop_min_from_child(mut self: Box<Self>, child: Box<Self>) -> (Option<Box<Self>>, Box<Self>) {
-        let (left, min) = child.pop_min();
-        self.left = left;
-        return (Some(self.rebalance()), min);
-    }
-
-    fn pop_min(mut self: Box<Self>) -> (Option<Box<Self>>, Box<Self>) {
-        match self.left.take() {
-            Some(node) => {
-                // recursively look for the min key
-                return self.pop_min_from_child(node);
-            } 
-            None => {
-                // no left child -- this is the min
-                return (self.right.take(), self)
-            }
+    /// detach the node with the smallest key, returning the rebalanced remainder and the node
+    fn pop_min<L: Listener<K,D>>(root: Box<Self, A>, ctx: &L) -> (Option<Box<Self, A>>, Box<Self, A>) {
+        let mut path = Vec::new();
+        let mut node = root;
+        while let Some(left) = node.left.take() {
+            path.push((node, Less));
+            node = left;
         }
+        // no left child -- this is the min
+        let right = node.right.take();
+        return (Self::reattach(path, right, ctx, true), node);
     }
 
-    fn merge_sibling(self: Box<Self>, other: Box<Self>) -> Box<Self> {
-        trace!("merge_sibling {} and {}", &self, &other);
-        let (tree, min) = self.pop_min();
+    fn merge_sibling<L: Listener<K,D>>(subtree: Box<Self, A>, other: Box<Self, A>, ctx: &L) -> Box<Self, A> {
+        trace!("merge_sibling {:?} and {:?}", &subtree, &other);
+        let (tree, min) = Self::pop_min(subtree, ctx);
         let mut root = min;
         root.left = Some(other);
         root.right = tree;
-        return root.rebalance();
+        return Self::rebalance(root, ctx);
+    }
+
+    /// join `left`, `mid` and `right` into one balanced tree, where every key in `left`
+    /// comes before `mid`'s and every key in `right` after it. `mid`'s children are
+    /// discarded. `mid` is hung from the spine of the taller side, so this takes time
+    /// proportional to the difference in height
+    #[cfg(feature = "rayon")]
+    pub(crate) fn join<L: Listener<K,D>>(left: OptBoxNode<K,D,A>, mut mid: Box<Self, A>, right: OptBoxNode<K,D,A>, ctx: &L) -> Box<Self, A> {
+        let height = |node: &OptBoxNode<K,D,A>| node.as_ref().map_or(0, |node| node.height);
+        let (left_height, right_height) = (height(&left), height(&right));
+        let mut path = Vec::new();
+        if left_height > right_height + 1 {
+            // walk down the right spine of `left` to a subtree at most one taller than `right`
+            let mut node = left;
+            while height(&node) > right_height + 1 {
+                let mut parent = node.expect("spine ended early");
+                node = parent.right.take();
+                path.push((parent, Greater));
+            }
+            mid.link(node, right);
+        } else if right_height > left_height + 1 {
+            let mut node = right;
+            while height(&node) > left_height + 1 {
+                let mut parent = node.expect("spine ended early");
+                node = parent.left.take();
+                path.push((parent, Less));
+            }
+            mid.link(left, node);
+        } else {
+            mid.link(left, right);
+        }
+        return Self::reattach(path, Some(mid), ctx, true).expect("joined tree is empty");
+    }
+
+    /// `join` without a middle node
+    #[cfg(feature = "rayon")]
+    pub(crate) fn join2<L: Listener<K,D>>(left: OptBoxNode<K,D,A>, right: OptBoxNode<K,D,A>, ctx: &L) -> OptBoxNode<K,D,A> {
+        let Some(right) = right else { return left };
+        let (rest, min) = Self::pop_min(right, ctx);
+        return Some(Self::join(left, min, rest, ctx));
     }
 
-    fn delete(self: Box<Self>) -> Option<Box<Self>> {
-        match (self.left, self.right) {
+    /// split a subtree into the keys before `key`, the node holding `key` if there is one,
+    /// detached from its children, and the keys after it. the pieces are rejoined on the way
+    /// back up from the split point, in O(log n) all told
+    #[cfg(feature = "rayon")]
+    pub(crate) fn split<Q, X>(root: OptBoxNode<K,D,A>, key: &Q, ctx: &X) -> Split<K,D,A>
+    where K: Borrow<Q>, Q: ?Sized, X: Compare<Q> + Listener<K,D>
+    {
+        let mut path = Vec::new();
+        let (mut left, mut found, mut right) = (None, None, None);
+        let mut curr = root;
+        while let Some(mut node) = curr {
+            let dir = ctx.compare(key, node.key.borrow());
+            curr = match dir {
+                Equal => {
+                    left = node.left.take();
+                    right = node.right.take();
+                    found = Some(node);
+                    break;
+                }
+                Less => node.left.take(),
+                Greater => node.right.take()
+            };
+            path.push((node, dir));
+        }
+        while let Some((mut node, dir)) = path.pop() {
+            if dir == Less {
+                // the node and everything to its right come after the key
+                let after = node.right.take();
+                right = Some(Self::join(right, node, after, ctx));
+            } else {
+                let before = node.left.take();
+                left = Some(Self::join(before, node, left, ctx));
+            }
+        }
+        return (left, found, right);
+    }
+
+    /// unlink this node from its children, returning the subtree that replaces it and the
+    /// detached node
+    fn delete<L: Listener<K,D>>(mut node: Box<Self, A>, ctx: &L) -> (OptBoxNode<K,D,A>, Box<Self, A>) {
+        let subtree = match (node.left.take(), node.right.take()) {
             (None, None) => None,
             (Some(left), None) => Some(left),
             (None, Some(right)) => Some(right),
-            (Some(left), Some(right)) => Some(right.merge_sibling(left))
+            (Some(left), Some(right)) => Some(Self::merge_sibling(right, left, ctx))
+        };
+        return (subtree, node);
+    }
+
+    /// delete the node with the given key, ordering keys with `cmp`
+    /// returns the new subtree root and the removed node. if the key is not found, the
+    /// subtree is returned unchanged along with `Error::KeyNotFound`
+    pub fn del_by<Q, C>(root: Box<Self, A>, key: &Q, cmp: &C) -> Deleted<K,D,A>
+    where K: Borrow<Q>, Q: ?Sized, C: Compare<Q>
+    {
+        return Self::del_in(root, key, &Quiet(cmp));
+    }
+
+    /// `del_by`, reporting each change to `ctx`
+    pub(crate) fn del_in<Q, X>(root: Box<Self, A>, key: &Q, ctx: &X) -> Deleted<K,D,A>
+    where K: Borrow<Q>, Q: ?Sized, X: Compare<Q> + Listener<K,D>
+    {
+        let mut path = Vec::new();
+        let mut node = root;
+        loop {
+            let dir = ctx.compare(key, node.key.borrow());
+            let child = match dir {
+                Equal => {
+                    ctx.removed(&node.key, &node.data);
+                    let (subtree, removed) = Self::delete(node, ctx);
+                    return (Self::reattach(path, subtree, ctx, true), Ok(removed));
+                }
+                // key < node.key -- go left
+                Less => node.left.take(),
+                // key > node.key -- go right
+                Greater => node.right.take()
+            };
+            path.push((node, dir));
+            match child {
+                Some(child) => node = child,
+                None => return (Self::reattach(path, None, ctx, false), Err(Error::KeyNotFound))
+            }
         }
     }
 
-    pub fn del(mut self: Box<Self>, key: K) -> Result<Option<Box<Self>>, String> {
-        match self.key.cmp(&key) {
-            Equal => return Ok(self.delete()),
-            Greater => {
-                // key < self.key -- go left
-                if let Some(child) = self.left {
-                    match child.del(key) {
-                        Ok(node) => {
-                            self.left = node;
-                            return Ok(Some(self.rebalance()));
-                        },
-                        Err(e) => return Err(e)
+    /// check the AVL invariants for the subtree rooted at this node: keys are in `cmp`
+    /// order, cached heights are correct and no node's children differ in height by more
+    /// than one
+    /// returns the height of the subtree
+    pub fn validate_by<C: Compare<K>>(&self, cmp: &C) -> Result<usize, Error> {
+        enum Step<'a, K, D, A: Allocator> {
+            Enter(&'a Node<K,D,A>, Option<&'a K>, Option<&'a K>),
+            Exit(&'a Node<K,D,A>),
+        }
+
+        let mut stack = vec![Step::Enter(self, None, None)];
+        let mut heights: Vec<usize> = Vec::new();
+        while let Some(step) = stack.pop() {
+            match step {
+                Step::Enter(node, lower, upper) => {
+                    if lower.is_some_and(|lower| cmp.compare(&node.key, lower) != Greater)
+                        || upper.is_some_and(|upper| cmp.compare(&node.key, upper) != Less) {
+                        return Err(Error::InvariantViolation(format!("key {:?} is out of order", node.key)));
                     }
-                } else { return Err(String::from("could not find node")) }
-            },
-            Less => {
-                // key > self.key -- go right
-                if let Some(child) = self.right {
-                    match child.del(key) {
-                        Ok(node) => {
-                            self.right = node;
-                            return Ok(Some(self.rebalance()));
-                        },
-                        Err(e) => return Err(e)
+                    stack.push(Step::Exit(node));
+                    if let Some(right) = &node.right { stack.push(Step::Enter(right, Some(&node.key), upper)) }
+                    if let Some(left) = &node.left { stack.push(Step::Enter(left, lower, Some(&node.key))) }
+                }
+                Step::Exit(node) => {
+                    // the left subtree finished first, so the right height is on top
+                    let right_height = if node.right.is_some() { heights.pop().expect("no height") } else { 0 };
+                    let left_height = if node.left.is_some() { heights.pop().expect("no height") } else { 0 };
+                    let bf = right_height as isize - left_height as isize;
+                    if bf.abs() > 1 {
+                        return Err(Error::InvariantViolation(format!("balance factor {} at key {:?}", bf, node.key)));
+                    }
+                    let height = cmp::max(left_height, right_height) + 1;
+                    if height != node.height {
+                        return Err(Error::InvariantViolation(
+                            format!("cached height {} at key {:?} should be {}", node.height, node.key, height)));
                     }
-                } else { return Err(String::from("could not find node")) }
+                    heights.push(height);
+                }
             }
         }
+        return Ok(heights.pop().expect("no height"));
+    }
+}
+
+/// shorthands for keys ordered by `Ord`
+impl<K: fmt::Debug + Ord, D: fmt::Debug, A: Allocator + Clone> Node<K,D,A> {
+    /// search for the given key
+    pub fn get<Q: Ord + ?Sized>(&self, key: &Q) -> Option<&Node<K,D,A>> where K: Borrow<Q> {
+        return self.get_by(key, &Natural);
+    }
+
+    /// search for the given key, returning a mutable reference to its node
+    pub fn get_mut<Q: Ord + ?Sized>(&mut self, key: &Q) -> Option<&mut Node<K,D,A>> where K: Borrow<Q> {
+        return self.get_mut_by(key, &Natural);
+    }
+
+    /// insert a new key/data pair
+    pub fn put(root: Box<Self, A>, key: K, data: D) -> (Box<Self, A>, OptBoxNode<K,D,A>) {
+        return Self::put_by(root, key, data, &Natural);
+    }
+
+    /// insert an already-allocated node
+    pub fn ins(root: Box<Self, A>, other: Box<Node<K,D,A>, A>) -> (Box<Self, A>, OptBoxNode<K,D,A>) {
+        return Self::ins_by(root, other, &Natural);
+    }
+
+    /// insert an already-allocated node, merging its data into an existing node with the
+    /// same key
+    pub fn ins_with<F: FnOnce(&mut D, D)>(root: Box<Self, A>, other: Box<Node<K,D,A>, A>, merge: F) -> (Box<Self, A>, bool) {
+        return Self::ins_with_by(root, other, &Natural, merge);
+    }
+
+    /// delete the node with the given key
+    pub fn del<Q: Ord + ?Sized>(root: Box<Self, A>, key: &Q) -> Deleted<K,D,A> where K: Borrow<Q> {
+        return Self::del_by(root, key, &Natural);
+    }
+
+    /// check the AVL invariants for the subtree rooted at this node
+    pub fn validate(&self) -> Result<usize, Error> {
+        return self.validate_by(&Natural);
     }
 }
 
 
-impl<K: Ord + Eq,D: Ord + Eq> PartialEq for Node<K,D>  {
+impl<K: Ord + Eq,D: Ord + Eq, A: Allocator> PartialEq for Node<K,D,A> {
     fn eq(&self, other: &Self) -> bool {
         (self.key == other.key) && (self.data == other.data)
     }
 }
 
-impl<K: Ord + Eq, D: Ord + Eq> Eq for Node<K,D> {  }
+impl<K: Ord + Eq, D: Ord + Eq, A: Allocator> Eq for Node<K,D,A> {  }
 
-impl<K: Ord + Eq,D: Ord + Eq> Ord for Node<K,D>  {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+impl<K: Ord + Eq,D: Ord + Eq, A: Allocator> Ord for Node<K,D,A> {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
         return (&self.key, &self.data).cmp(&(&other.key, &other.data));
     }
 }
 
-impl<K: Ord + Eq,D: Ord + Eq> PartialOrd for Node<K,D>  {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+impl<K: Ord + Eq,D: Ord + Eq, A: Allocator> PartialOrd for Node<K,D,A> {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
         return Some(self.cmp(other));
     }
 }
@@ -441,7 +898,7 @@ mod tests {
     use super::*;
     use test_env_log::test;
 
-    fn test_ordering<K: Ord+Eq+fmt::Display+fmt::Debug+Clone, D: Ord+Eq+fmt::Display+fmt::Debug+Clone>(first: (K,D), second: (K,D)) {
+    fn test_ordering<K: Ord+Eq+fmt::Debug+Clone, D: Ord+Eq+fmt::Debug+Clone>(first: (K,D), second: (K,D)) {
         let n1 = Node::new(first.0.clone(), first.1.clone());
         let n2 = Node::new(second.0.clone(), second.1.clone());
 
@@ -479,7 +936,7 @@ mod tests {
         left.update_height();
         assert_eq!(left.height, 2);
         assert_eq!(left.as_mut().balance_factor(), -1);
-        assert_eq!(left.as_mut().left_heavy(), true);
+        assert!(left.as_mut().left_heavy());
 
         root.left = Some(left);
         root.update_height();
@@ -501,11 +958,11 @@ mod tests {
         assert_eq!(root.left.as_ref().unwrap(), &Node::newbox(1, "qwerty"));
         assert_eq!(root.left.as_ref().unwrap().left.as_ref().unwrap(), &Node::newbox(0, "zxcv"));
 
-        let new_root = root.rotate_right();
+        let mut new_root = Node::rotate_right(root);
 
         assert_eq!(new_root, Node::newbox(1isize, "qwerty"));
-        assert_eq!(new_root.right.unwrap(), Node::newbox(2,"asdf"));
-        assert_eq!(new_root.left.unwrap(), Node::newbox(0isize,"zxcv"));
+        assert_eq!(new_root.right.take().unwrap(), Node::newbox(2,"asdf"));
+        assert_eq!(new_root.left.take().unwrap(), Node::newbox(0isize,"zxcv"));
     }
 
     #[test]
@@ -521,11 +978,11 @@ mod tests {
         assert_eq!(root.right.as_ref().unwrap(), &Node::newbox(1, "right"));
         assert_eq!(root.right.as_ref().unwrap().right.as_ref().unwrap(), &Node::newbox(0, "right_right"));
 
-        let new_root = root.rotate_left();
+        let mut new_root = Node::rotate_left(root);
 
         assert_eq!(new_root, Node::newbox(1isize, "right"));
-        assert_eq!(new_root.left.unwrap(), Node::newbox(2,"root"));
-        assert_eq!(new_root.right.unwrap(), Node::newbox(0isize,"right_right"));
+        assert_eq!(new_root.left.take().unwrap(), Node::newbox(2,"root"));
+        assert_eq!(new_root.right.take().unwrap(), Node::newbox(0isize,"right_right"));
     }
 
     #[test]
@@ -541,11 +998,11 @@ mod tests {
         assert_eq!(root.right.as_ref().unwrap(), &Node::newbox(1, "right"));
         assert_eq!(root.right.as_ref().unwrap().left.as_ref().unwrap(), &Node::newbox(0, "right_left"));
 
-        let new_root = root.rotate_right_left();
+        let mut new_root = Node::rotate_right_left(root);
 
         assert_eq!(new_root, Node::newbox(0, "right_left"));
-        assert_eq!(new_root.left.unwrap(), Node::newbox(2,"root"));
-        assert_eq!(new_root.right.unwrap(), Node::newbox(1,"right"));
+        assert_eq!(new_root.left.take().unwrap(), Node::newbox(2,"root"));
+        assert_eq!(new_root.right.take().unwrap(), Node::newbox(1,"right"));
     }
 
     #[test]
@@ -561,11 +1018,11 @@ mod tests {
         assert_eq!(root.left.as_ref().unwrap(), &Node::newbox(1, "left"));
         assert_eq!(root.left.as_ref().unwrap().right.as_ref().unwrap(), &Node::newbox(0, "left_right"));
 
-        let new_root = root.rotate_left_right();
+        let mut new_root = Node::rotate_left_right(root);
 
         assert_eq!(new_root, Node::newbox(0, "left_right"));
-        assert_eq!(new_root.right.unwrap(), Node::newbox(2,"root"));
-        assert_eq!(new_root.left.unwrap(), Node::newbox(1,"left"));
+        assert_eq!(new_root.right.take().unwrap(), Node::newbox(2,"root"));
+        assert_eq!(new_root.left.take().unwrap(), Node::newbox(1,"left"));
     }
 
     use std::collections::HashMap;
@@ -578,39 +1035,47 @@ mod tests {
         return data.iter().map(|(x,y)| (x.clone(), y.clone())).collect();
     }
 
+    #[cfg(feature = "std")]
     fn test_put<K,D>(data: HashMap<K,D>) 
-    where K: Ord + Eq + Clone + fmt::Display + fmt::Debug,
-          D: Ord + Eq + Clone + fmt::Display + fmt::Debug,
+    where K: Ord + Eq + Clone + fmt::Debug,
+          D: Ord + Eq + Clone + fmt::Debug,
     
     {
         let t = AVLTree::from(&data);
         let mut v = vec_from_hashmap(data);
 
-        v.sort_by(|a,b| a.cmp(&b));
+        v.sort();
         assert_eq!(t.items(), v);
 
     }
+    #[cfg(feature = "std")]
     #[quickcheck]
     fn qc_test_put_isize_isize(data: HashMap<isize, isize>) { test_put(data) }
+    #[cfg(feature = "std")]
     #[quickcheck]
     fn qc_test_put_isize_string(data: HashMap<isize, String>) { test_put(data) }
+    #[cfg(feature = "std")]
     #[quickcheck]
     fn qc_test_put_string_string(data: HashMap<String, String>) { test_put(data) }
 
 
+    #[cfg(feature = "std")]
     fn test_get<K,D>(data: HashMap<K,D>) 
-    where K: Ord + Eq + Clone + fmt::Display + fmt::Debug,
-          D: Ord + Eq + Clone + fmt::Display + fmt::Debug,
+    where K: Ord + Eq + Clone + fmt::Debug,
+          D: Ord + Eq + Clone + fmt::Debug,
     {
         let t = AVLTree::from(&data);
         for (k,d) in data {
             assert_eq!(t.get(k).unwrap(), d);
         }
     }
+    #[cfg(feature = "std")]
     #[quickcheck]
     fn qc_test_get_isize_isize(data: HashMap<isize, isize>) { test_get(data) }
+    #[cfg(feature = "std")]
     #[quickcheck]
     fn qc_test_get_isize_string(data: HashMap<isize, String>) { test_get(data) }
+    #[cfg(feature = "std")]
     #[quickcheck]
     fn qc_test_get_string_string(data: HashMap<String, String>) { test_get(data) }
 
@@ -619,10 +1084,10 @@ mod tests {
         if data.len() < 2 { return }
 
         let mut v = vec_from_hashmap(data);
-        let t = AVLTree::from(&v);
+        let mut t = AVLTree::from(&v);
 
-        v.sort_by(|a,b| a.cmp(&b));
-        let (root, min) = t.root.unwrap().pop_min();
+        v.sort();
+        let (root, min) = Node::pop_min(t.root.take().unwrap(), &Quiet(&Natural));
         assert_eq!(v[0], (min.key, min.data));
         v.remove(0);
 
@@ -641,12 +1106,12 @@ mod tests {
         let mut tree = AVLTree::from(&vec);
 
         let mut rng = rand::thread_rng();
-        vec.sort_by(|a,b| (a.cmp(&b)));
+        vec.sort();
 
         for i in 0..rng.gen_range(0, vec.len()) {
             if i < vec.len() - 1 {continue}
-            let (delkey, _delval) = vec.remove(rng.gen_range(1, vec.len()));
-            tree.del(delkey);
+            let (delkey, delval) = vec.remove(rng.gen_range(1, vec.len()));
+            assert_eq!(tree.del(delkey), Ok(delval));
             assert_eq!(tree.items(), vec);
         }
 
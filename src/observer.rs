@@ -0,0 +1,176 @@
+use crate::Rotation;
+use alloc::boxed::Box;
+
+/// callbacks fired as an `AVLTree` changes, for keeping secondary indexes, caches or audit
+/// logs in step with it. observers only ever see shared references, so they can't reorder
+/// keys or reshape the tree. every method does nothing by default
+pub trait Observer<K, D> {
+    /// a new key was added
+    fn on_insert(&mut self, _key: &K, _data: &D) {}
+    /// an existing key's data was replaced by `put` or `ins`
+    fn on_replace(&mut self, _key: &K, _old: &D, _new: &D) {}
+    /// a key is being removed, by `del`, `retain`, `extract_if` or `clear`
+    fn on_remove(&mut self, _key: &K, _data: &D) {}
+    /// the node holding `pivot` was rotated down while rebalancing and the node holding
+    /// `promoted` took its place
+    fn on_rotate(&mut self, _rotation: Rotation, _pivot: &K, _promoted: &K) {}
+}
+
+pub(crate) type BoxObserver<K, D> = Box<dyn Observer<K, D> + Send + Sync>;
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AVLTree;
+    use std::sync::{Arc, Mutex};
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    enum Event {
+        Insert(u8, u8),
+        Replace(u8, u8, u8),
+        Remove(u8, u8),
+        Rotate(Rotation, u8, u8),
+    }
+
+    #[derive(Clone, Default)]
+    struct Log(Arc<Mutex<Vec<Event>>>);
+
+    impl Log {
+        fn take(&self) -> Vec<Event> {
+            return std::mem::take(&mut *self.0.lock().unwrap());
+        }
+    }
+
+    impl Observer<u8, u8> for Log {
+        fn on_insert(&mut self, key: &u8, data: &u8) {
+            self.0.lock().unwrap().push(Event::Insert(*key, *data));
+        }
+        fn on_replace(&mut self, key: &u8, old: &u8, new: &u8) {
+            self.0.lock().unwrap().push(Event::Replace(*key, *old, *new));
+        }
+        fn on_remove(&mut self, key: &u8, data: &u8) {
+            self.0.lock().unwrap().push(Event::Remove(*key, *data));
+        }
+        fn on_rotate(&mut self, rotation: Rotation, pivot: &u8, promoted: &u8) {
+            self.0.lock().unwrap().push(Event::Rotate(rotation, *pivot, *promoted));
+        }
+    }
+
+    #[test]
+    fn test_events() {
+        let log = Log::default();
+        let mut tree = AVLTree::new();
+        tree.observe(log.clone());
+
+        tree.put(3, 30);
+        tree.put(1, 10);
+        tree.put(2, 20);
+        assert_eq!(log.take(), vec![
+            Event::Insert(3, 30),
+            Event::Insert(1, 10),
+            Event::Insert(2, 20),
+            Event::Rotate(Rotation::LeftRight, 3, 2)]);
+
+        tree.put(2, 21);
+        assert_eq!(tree.del(1), Ok(10));
+        assert_eq!(tree.del(1), Err(crate::Error::KeyNotFound));
+        assert_eq!(log.take(), vec![Event::Replace(2, 20, 21), Event::Remove(1, 10)]);
+
+        tree.put(4, 40);
+        tree.retain(|key, _| *key != 3);
+        tree.clear();
+        assert_eq!(log.take(), vec![
+            Event::Insert(4, 40),
+            Event::Rotate(Rotation::Left, 2, 3),
+            Event::Remove(3, 30),
+            Event::Remove(2, 21),
+            Event::Remove(4, 40)]);
+    }
+
+    /// a secondary index from data back to key, kept in step only by the observer
+    #[derive(Clone, Default)]
+    struct ByData(Arc<Mutex<BTreeMap<u8, u8>>>);
+
+    impl Observer<u8, u8> for ByData {
+        fn on_insert(&mut self, key: &u8, data: &u8) {
+            self.0.lock().unwrap().insert(*data, *key);
+        }
+        fn on_replace(&mut self, key: &u8, old: &u8, new: &u8) {
+            let mut index = self.0.lock().unwrap();
+            index.remove(old);
+            index.insert(*new, *key);
+        }
+        fn on_remove(&mut self, _key: &u8, data: &u8) {
+            self.0.lock().unwrap().remove(data);
+        }
+    }
+
+    #[quickcheck]
+    fn qc_test_secondary_index(ops: Vec<(bool, u8)>) {
+        let index = ByData::default();
+        let mut tree = AVLTree::new();
+        tree.observe(index.clone());
+        for (put, key) in ops {
+            // data is a bijection of the key so the index stays one-to-one
+            if put { tree.put(key, !key); } else { let _ = tree.del(key); }
+        }
+        let expected: BTreeMap<u8, u8> = tree.iter().map(|(key, data)| (*data, *key)).collect();
+        assert_eq!(*index.0.lock().unwrap(), expected);
+    }
+
+    /// panics whenever it hears about the key it's armed with
+    struct Tripwire(u8);
+
+    impl Observer<u8, u8> for Tripwire {
+        fn on_insert(&mut self, key: &u8, _data: &u8) {
+            if *key == self.0 { panic!("tripped on insert") }
+        }
+        fn on_remove(&mut self, key: &u8, _data: &u8) {
+            if *key == self.0 { panic!("tripped on remove") }
+        }
+        fn on_rotate(&mut self, _rotation: Rotation, pivot: &u8, _promoted: &u8) {
+            if *pivot == self.0 { panic!("tripped on rotate") }
+        }
+    }
+
+    #[test]
+    fn test_panicking_observer() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let mut tree: AVLTree<u8, u8> = (0..10).map(|key| (key, key)).collect();
+        tree.observe(Tripwire(99));
+        let mut expected: BTreeMap<u8, u8> = tree.iter().map(|(key, data)| (*key, *data)).collect();
+        let check = |tree: &AVLTree<u8, u8>, expected: &BTreeMap<u8, u8>| {
+            assert_eq!(tree.validate(), Ok(()));
+            assert_eq!(tree.len(), expected.len());
+            assert!(tree.iter().eq(expected.iter()));
+        };
+
+        // the change is made before the observer hears of it and panics
+        assert!(catch_unwind(AssertUnwindSafe(|| tree.put(99, 0))).is_err());
+        expected.insert(99, 0);
+        check(&tree, &expected);
+
+        assert!(catch_unwind(AssertUnwindSafe(|| tree.del(99))).is_err());
+        expected.remove(&99);
+        check(&tree, &expected);
+
+        assert!(catch_unwind(AssertUnwindSafe(|| tree.put(99, 0))).is_err());
+        expected.insert(99, 0);
+        assert!(catch_unwind(AssertUnwindSafe(|| tree.retain(|key, _| key % 2 == 0))).is_err());
+        expected.retain(|key, _| key % 2 == 0);
+        check(&tree, &expected);
+
+        // a rotation about 99 panics partway through a run of inserts
+        let mut tree = AVLTree::new();
+        tree.put(99, 0);
+        tree.observe(Tripwire(99));
+        assert!(catch_unwind(AssertUnwindSafe(|| { tree.put(100, 0); tree.put(101, 0); })).is_err());
+        check(&tree, &[(99, 0), (100, 0), (101, 0)].iter().cloned().collect());
+
+        assert!(catch_unwind(AssertUnwindSafe(|| tree.clear())).is_err());
+        check(&tree, &BTreeMap::new());
+    }
+}
@@ -0,0 +1,414 @@
+use crate::{AVLTree, Compare, Natural, Node, NodeIter};
+use crate::iter::IntoNodes;
+use crate::node::{OptBoxNode, Quiet};
+use allocator_api2::alloc::{Allocator, Global};
+use allocator_api2::boxed::Box;
+use core::cmp::Ordering::{Equal, Less};
+use core::fmt;
+use rayon::iter::plumbing::{bridge_unindexed, Folder, UnindexedConsumer, UnindexedProducer};
+use rayon::prelude::*;
+
+/// below this many nodes, a subtree is built on the current thread
+const SEQUENTIAL: usize = 1 << 12;
+
+/// build a perfectly balanced tree from `slots`, in order, turning each into a node with
+/// `make`. halves above `SEQUENTIAL` are built concurrently. the shape is the same as
+/// `Node::from_sorted_nodes` would give
+fn build<K, D, A, T, F>(slots: &mut [Option<T>], make: &F) -> OptBoxNode<K,D,A>
+where K: fmt::Debug + Send, D: fmt::Debug + Send, A: Allocator + Clone + Send, T: Send,
+      F: Fn(T) -> Box<Node<K,D,A>, A> + Sync
+{
+    let take = |slot: &mut Option<T>| make(slot.take().expect("slot built twice"));
+    let len = slots.len();
+    if len <= SEQUENTIAL {
+        return Node::from_sorted_nodes(&mut slots.iter_mut().map(take), len);
+    }
+    let (left, rest) = slots.split_at_mut(len / 2);
+    let (middle, right) = rest.split_first_mut().expect("no middle slot");
+    let (left, right) = rayon::join(|| build(left, make), || build(right, make));
+    let mut node = take(middle);
+    node.link(left, right);
+    return Some(node);
+}
+
+impl<K, D> AVLTree<K, D>
+where K: Ord + Eq + Clone + fmt::Debug + Send, D: Clone + fmt::Debug + Send
+{
+    /// `from_sorted_iter`, allocating nodes and building the left and right halves of the
+    /// tree on rayon's thread pool
+    pub fn from_sorted_par<I: IntoParallelIterator<Item = (K,D)>>(iter: I) -> Self {
+        return Self::from_sorted_par_in(iter, Natural, Global);
+    }
+}
+
+impl<K, D, C, A> AVLTree<K, D, C, A>
+where K: Clone + fmt::Debug + Send, D: Clone + fmt::Debug + Send, C: Compare<K>,
+      A: Allocator + Clone + Send + Sync
+{
+    /// `from_sorted_par` for a tree ordered by `cmp` whose nodes are allocated by `alloc`
+    pub fn from_sorted_par_in<I: IntoParallelIterator<Item = (K,D)>>(iter: I, cmp: C, alloc: A) -> Self {
+        let mut slots: Vec<Option<(K,D)>> = iter.into_par_iter().map(Some).collect();
+        let mut tree = Self::with_comparator_in(cmp, alloc);
+        let alloc = &tree.allocator().clone();
+        tree.root = build(&mut slots, &|(key, data)| Node::newbox_in(key, data, alloc.clone()));
+        tree.len = slots.len();
+        tree.allocated(tree.len);
+        return tree;
+    }
+}
+
+impl<K, D, C, A> AVLTree<K, D, C, A>
+where K: Sync, D: Sync, A: Allocator + Sync
+{
+    /// a parallel in-order iterator over the key/data pairs. work is split at subtree
+    /// boundaries, so no pass over the tree is needed to divide it up
+    pub fn par_iter(&self) -> ParIter<'_, K, D, A> {
+        return ParIter { root: self.root.as_deref() };
+    }
+}
+
+/// parallel iterator over a tree's key/data pairs, in order when collected
+pub struct ParIter<'a, K, D, A: Allocator = Global> {
+    root: Option<&'a Node<K,D,A>>,
+}
+
+impl<'a, K: Sync, D: Sync, A: Allocator + Sync> ParallelIterator for ParIter<'a, K, D, A> {
+    type Item = (&'a K, &'a D);
+
+    fn drive_unindexed<C: UnindexedConsumer<Self::Item>>(self, consumer: C) -> C::Result {
+        return bridge_unindexed(Pieces(self.root.map(Piece::Subtree).into_iter().collect()), consumer);
+    }
+}
+
+impl<'a, K: Sync, D: Sync, C, A: Allocator + Sync> IntoParallelIterator for &'a AVLTree<K,D,C,A> {
+    type Item = (&'a K, &'a D);
+    type Iter = ParIter<'a, K, D, A>;
+
+    fn into_par_iter(self) -> ParIter<'a, K, D, A> {
+        return self.par_iter();
+    }
+}
+
+enum Piece<'a, K, D, A: Allocator> {
+    /// every pair in a subtree
+    Subtree(&'a Node<K,D,A>),
+    /// just a node's own pair
+    Item(&'a Node<K,D,A>),
+}
+
+/// a run of pieces that together cover a stretch of the tree in order
+struct Pieces<'a, K, D, A: Allocator>(Vec<Piece<'a, K, D, A>>);
+
+impl<'a, K: Sync, D: Sync, A: Allocator + Sync> UnindexedProducer for Pieces<'a, K, D, A> {
+    type Item = (&'a K, &'a D);
+
+    fn split(mut self) -> (Self, Option<Self>) {
+        // a lone subtree is opened up into its left subtree, its root and its right subtree
+        if let [Piece::Subtree(node)] = self.0[..] {
+            self.0 = vec![node.left.as_deref().map(Piece::Subtree), Some(Piece::Item(node)), node.right.as_deref().map(Piece::Subtree)]
+                .into_iter().flatten().collect();
+        }
+        if self.0.len() < 2 { return (self, None) }
+        let right = self.0.split_off(self.0.len() / 2);
+        return (self, Some(Pieces(right)));
+    }
+
+    fn fold_with<F: Folder<Self::Item>>(self, mut folder: F) -> F {
+        for piece in self.0 {
+            folder = match piece {
+                Piece::Subtree(node) => folder.consume_iter(NodeIter::with_root(node)),
+                Piece::Item(node) => folder.consume((&node.key, &node.data)),
+            };
+            if folder.full() { break }
+        }
+        return folder;
+    }
+}
+
+/// the pairs are collected and sorted in parallel, then merged with the tree's own and the
+/// tree rebuilt, in O(n + m) besides the sort. as with `extend`, when a key appears more
+/// than once the last pair wins. a tree with observers, or recording a trace, is extended
+/// one `put` at a time, so that every change is reported
+impl<K, D, C, A> ParallelExtend<(K,D)> for AVLTree<K,D,C,A>
+where K: Clone + fmt::Debug + Send, D: Clone + fmt::Debug + Send, C: Compare<K> + Sync,
+      A: Allocator + Clone + Send
+{
+    fn par_extend<I: IntoParallelIterator<Item = (K,D)>>(&mut self, par_iter: I) {
+        // collecting keeps the iterator's order, and the sort is stable, so duplicates stay
+        // in the order they arrived
+        let mut items: Vec<(K,D)> = par_iter.into_par_iter().collect();
+        let cmp = self.comparator();
+        items.par_sort_by(|a, b| cmp.compare(&a.0, &b.0));
+        if self.is_observed() {
+            self.extend(items);
+            return;
+        }
+
+        let mut old = IntoNodes::new(self.root.take()).peekable();
+        let mut slots = Vec::with_capacity(self.len + items.len());
+        let mut allocated = 0;
+        let mut new = items.into_iter().peekable();
+        let cmp = self.comparator();
+        let alloc = self.allocator();
+        while let Some((key, data)) = new.next() {
+            // a later pair with the same key wins
+            if new.peek().is_some_and(|next| cmp.compare(&next.0, &key) == Equal) { continue }
+            while let Some(node) = old.next_if(|node| cmp.compare(&node.key, &key) == Less) {
+                slots.push(Some(node));
+            }
+            match old.next_if(|node| cmp.compare(&node.key, &key) == Equal) {
+                Some(mut node) => { node.data = data; slots.push(Some(node)); }
+                None => { slots.push(Some(Node::newbox_in(key, data, alloc.clone()))); allocated += 1; }
+            }
+        }
+        slots.extend(old.map(Some));
+        self.root = build(&mut slots, &|node| node);
+        self.len = slots.len();
+        self.allocated(allocated);
+    }
+}
+
+/// subtrees no taller than this, so of at most 2^12 - 1 nodes, are combined on the
+/// current thread
+const SEQUENTIAL_HEIGHT: usize = 12;
+
+fn height<K, D, A: Allocator>(node: &OptBoxNode<K,D,A>) -> usize {
+    return node.as_ref().map_or(0, |node| node.height);
+}
+
+/// run `a` and `b`, on rayon's thread pool if `parallel` is set
+fn fork<RA, RB, FA, FB>(parallel: bool, a: FA, b: FB) -> (RA, RB)
+where RA: Send, RB: Send, FA: FnOnce() -> RA + Send, FB: FnOnce() -> RB + Send
+{
+    if parallel { return rayon::join(a, b) }
+    return (a(), b());
+}
+
+/* the set operations below are the divide-and-conquer algorithms of Blelloch, Ferizovic
+ * and Sun, "Just Join for Parallel Ordered Sets": split one tree at the other's root, combine
+ * the two halves independently and join the results back together. each returns the
+ * combined subtree and the number of keys found in both trees
+ */
+fn union<K, D, C, A>(a: OptBoxNode<K,D,A>, b: OptBoxNode<K,D,A>, cmp: &C) -> (OptBoxNode<K,D,A>, usize)
+where K: fmt::Debug + Send, D: fmt::Debug + Send, C: Compare<K> + Sync, A: Allocator + Clone + Send
+{
+    let parallel = height(&a).max(height(&b)) > SEQUENTIAL_HEIGHT;
+    let (a, mut b) = match (a, b) {
+        (Some(a), Some(b)) => (a, b),
+        (a, b) => return (a.or(b), 0)
+    };
+    let (b_left, b_right) = (b.left.take(), b.right.take());
+    // `b`'s root is kept, so its data wins over `a`'s
+    let (a_left, found, a_right) = Node::split(Some(a), &b.key, &Quiet(cmp));
+    let ((left, l), (right, r)) = fork(parallel, || union(a_left, b_left, cmp), || union(a_right, b_right, cmp));
+    return (Some(Node::join(left, b, right, &Quiet(cmp))), l + r + found.is_some() as usize);
+}
+
+fn intersection<K, D, C, A>(a: OptBoxNode<K,D,A>, b: OptBoxNode<K,D,A>, cmp: &C) -> (OptBoxNode<K,D,A>, usize)
+where K: fmt::Debug + Send, D: fmt::Debug + Send, C: Compare<K> + Sync, A: Allocator + Clone + Send
+{
+    let parallel = height(&a).max(height(&b)) > SEQUENTIAL_HEIGHT;
+    let (mut a, b) = match (a, b) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return (None, 0)
+    };
+    let (a_left, a_right) = (a.left.take(), a.right.take());
+    let (b_left, found, b_right) = Node::split(Some(b), &a.key, &Quiet(cmp));
+    let ((left, l), (right, r)) = fork(parallel, || intersection(a_left, b_left, cmp), || intersection(a_right, b_right, cmp));
+    return match found {
+        Some(_) => (Some(Node::join(left, a, right, &Quiet(cmp))), l + r + 1),
+        None => (Node::join2(left, right, &Quiet(cmp)), l + r)
+    };
+}
+
+fn difference<K, D, C, A>(a: OptBoxNode<K,D,A>, b: OptBoxNode<K,D,A>, cmp: &C) -> (OptBoxNode<K,D,A>, usize)
+where K: fmt::Debug + Send, D: fmt::Debug + Send, C: Compare<K> + Sync, A: Allocator + Clone + Send
+{
+    let parallel = height(&a).max(height(&b)) > SEQUENTIAL_HEIGHT;
+    let (a, mut b) = match (a, b) {
+        (Some(a), Some(b)) => (a, b),
+        (a, _) => return (a, 0)
+    };
+    let (b_left, b_right) = (b.left.take(), b.right.take());
+    let (a_left, found, a_right) = Node::split(Some(a), &b.key, &Quiet(cmp));
+    let ((left, l), (right, r)) = fork(parallel, || difference(a_left, b_left, cmp), || difference(a_right, b_right, cmp));
+    return (Node::join2(left, right, &Quiet(cmp)), l + r + found.is_some() as usize);
+}
+
+/// set operations that split the trees apart and join them back together, working on
+/// disjoint subtrees concurrently. they take O(m log(n/m + 1)) work for trees of sizes
+/// m <= n, so combining a small tree with a large one is cheap. nodes are moved rather than
+/// copied, and keys are ordered by `self`'s comparator, which `other` must agree with. a
+/// tree with observers, or recording a trace, is combined one `put` or removal at a time,
+/// so that every change is reported
+impl<K, D, C, A> AVLTree<K, D, C, A>
+where K: Clone + fmt::Debug + Send, D: Clone + fmt::Debug + Send, C: Compare<K> + Sync,
+      A: Allocator + Clone + Send
+{
+    /// every pair in either tree. where a key is in both, `other`'s data wins, as if its
+    /// pairs had been `put` into `self`
+    pub fn par_union(mut self, mut other: Self) -> Self {
+        if self.is_observed() {
+            self.extend(other);
+            return self;
+        }
+        let (root, common) = union(self.root.take(), other.root.take(), self.comparator());
+        self.root = root;
+        self.len = self.len + other.len - common;
+        return self;
+    }
+
+    /// the pairs of `self` whose keys are also in `other`
+    pub fn par_intersection(mut self, mut other: Self) -> Self {
+        if self.is_observed() {
+            self.retain(|key, _| other.find(key).is_some());
+            return self;
+        }
+        let (root, common) = intersection(self.root.take(), other.root.take(), self.comparator());
+        self.root = root;
+        self.len = common;
+        return self;
+    }
+
+    /// the pairs of `self` whose keys are not in `other`
+    pub fn par_difference(mut self, mut other: Self) -> Self {
+        if self.is_observed() {
+            self.retain(|key, _| other.find(key).is_none());
+            return self;
+        }
+        let (root, common) = difference(self.root.take(), other.root.take(), self.comparator());
+        self.root = root;
+        self.len -= common;
+        return self;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Observer, Step};
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+    #[quickcheck]
+    fn qc_test_par_iter(xs: BTreeMap<u16, u16>) {
+        let tree = AVLTree::from(xs.clone());
+        let pairs: Vec<(&u16, &u16)> = tree.par_iter().collect();
+        assert_eq!(pairs, xs.iter().collect::<Vec<_>>());
+        assert_eq!((&tree).into_par_iter().map(|(_, data)| *data as u64).sum::<u64>(),
+                   xs.values().map(|data| *data as u64).sum());
+        assert_eq!(tree.par_iter().find_any(|(key, _)| **key == 7).is_some(), xs.contains_key(&7));
+    }
+
+    #[test]
+    fn test_from_sorted_par() {
+        // big enough that some halves are built on other threads
+        let n = 10 * SEQUENTIAL as u32 + 3;
+        let tree = AVLTree::from_sorted_par((0..n).into_par_iter().map(|key| (key, key * 2)));
+        assert_eq!(tree.validate(), Ok(()));
+        assert_eq!(tree.len(), n as usize);
+        assert_eq!(tree.height(), AVLTree::from_sorted_iter((0..n).map(|key| (key, key * 2))).height());
+        assert!(tree.iter().map(|(key, data)| (*key, *data)).eq((0..n).map(|key| (key, key * 2))));
+        assert_eq!(tree.par_iter().filter(|(key, _)| **key % 2 == 0).count(), (n as usize).div_ceil(2));
+        assert!(AVLTree::<u32, u32>::from_sorted_par(Vec::new()).is_empty());
+    }
+
+    #[quickcheck]
+    fn qc_test_par_extend(xs: BTreeMap<u8, u8>, ys: Vec<(u8, u8)>) {
+        let mut tree = AVLTree::from(xs.clone());
+        tree.par_extend(ys.clone());
+        let mut expected = xs;
+        expected.extend(ys);
+        assert_eq!(tree.validate(), Ok(()));
+        assert_eq!(tree.len(), expected.len());
+        assert_eq!(BTreeMap::from(tree), expected);
+    }
+
+    #[test]
+    fn test_par_extend_large() {
+        let n = 4 * SEQUENTIAL as u32;
+        let mut tree = AVLTree::from_sorted_iter((0..n).step_by(2).map(|key| (key, 0)));
+        tree.par_extend((0..n).into_par_iter().rev().map(|key| (key, 1)));
+        assert_eq!(tree.validate(), Ok(()));
+        assert_eq!(tree.len(), n as usize);
+        assert!(tree.iter().all(|(_, data)| *data == 1));
+    }
+
+    #[quickcheck]
+    fn qc_test_set_operations(xs: BTreeMap<u8, u8>, ys: BTreeMap<u8, u8>) {
+        let (a, b) = (AVLTree::from(xs.clone()), AVLTree::from(ys.clone()));
+        let check = |tree: AVLTree<u8, u8>, expected: BTreeMap<u8, u8>| {
+            assert_eq!(tree.validate(), Ok(()));
+            #[cfg(feature = "merkle")]
+            assert_eq!(tree.root_hash(), AVLTree::from_sorted_iter(tree.items()).root_hash());
+            assert_eq!(tree.len(), expected.len());
+            assert_eq!(BTreeMap::from(tree), expected);
+        };
+
+        let mut union = xs.clone();
+        union.extend(ys.clone());
+        check(a.clone().par_union(b.clone()), union);
+        let intersection = xs.iter().filter(|(key, _)| ys.contains_key(key)).map(|(k, d)| (*k, *d)).collect();
+        check(a.clone().par_intersection(b.clone()), intersection);
+        let difference = xs.iter().filter(|(key, _)| !ys.contains_key(key)).map(|(k, d)| (*k, *d)).collect();
+        check(a.par_difference(b), difference);
+    }
+
+    #[test]
+    fn test_set_operations_large() {
+        // tall enough that the top levels are combined on other threads
+        let n = 1 << (SEQUENTIAL_HEIGHT + 3);
+        let evens = AVLTree::from_sorted_iter((0..n).step_by(2).map(|key| (key, 0)));
+        let threes = AVLTree::from_sorted_iter((0..n).step_by(3).map(|key| (key, 1)));
+
+        let union = evens.clone().par_union(threes.clone());
+        assert_eq!(union.validate(), Ok(()));
+        assert!(union.iter().map(|(key, _)| *key).eq((0..n).filter(|key| key % 2 == 0 || key % 3 == 0)));
+        assert!(union.iter().all(|(key, data)| *data == (key % 3 == 0) as u32));
+
+        let intersection = evens.clone().par_intersection(threes.clone());
+        assert_eq!(intersection.validate(), Ok(()));
+        assert!(intersection.iter().map(|(key, _)| *key).eq((0..n).step_by(6)));
+        assert!(intersection.iter().all(|(_, data)| *data == 0));
+
+        let difference = evens.par_difference(threes);
+        assert_eq!(difference.validate(), Ok(()));
+        assert_eq!(difference.len(), (0..n).filter(|key| key % 2 == 0 && key % 3 != 0).count());
+
+        // a small tree against a large one, so the heights differ widely at every join
+        let small = AVLTree::from_sorted_iter((0..n).step_by(997).map(|key| (key, 2)));
+        let union = union.par_union(small);
+        assert_eq!(union.validate(), Ok(()));
+        assert_eq!(union[&997], 2);
+    }
+
+    #[test]
+    fn test_par_extend_observed() {
+        struct Count(Arc<AtomicUsize>);
+
+        impl Observer<u8, u8> for Count {
+            fn on_insert(&mut self, _key: &u8, _data: &u8) {
+                self.0.fetch_add(1, Relaxed);
+            }
+        }
+
+        let inserted = Arc::new(AtomicUsize::new(0));
+        let mut tree = AVLTree::new();
+        tree.observe(Count(Arc::clone(&inserted)));
+        tree.par_extend(vec![(3, 0), (1, 0), (3, 1), (2, 0)]);
+        assert_eq!(tree.items(), vec![(1, 0), (2, 0), (3, 1)]);
+        assert_eq!(inserted.load(Relaxed), 3);
+
+        let mut tree = AVLTree::new();
+        tree.start_trace();
+        tree.par_extend(vec![(2, 0), (1, 0)]);
+        let mut tree = tree.par_difference(AVLTree::from([(1, 0)]));
+        assert_eq!(tree.stop_trace().steps(), &[
+            Step::Insert { key: 1 },
+            Step::Insert { key: 2 },
+            Step::Remove { key: 1 },
+        ]);
+    }
+}
@@ -0,0 +1,685 @@
+use crate::{Compare, Error, IterType, Natural};
+use crate::walk::{map_traits, Branch, Drain, Span, Walk};
+use core::borrow::Borrow;
+use core::cmp::Ordering::{Equal, Greater, Less};
+use core::fmt;
+use core::mem;
+use core::ops::RangeBounds;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::vec::Vec;
+
+/* a left-leaning red-black tree, after Sedgewick: red links only ever lean left, which
+ * halves the number of cases insert and delete have to handle. it is still a red-black
+ * tree, so paths are at most twice as long as in a perfectly balanced tree and the
+ * recursion in `insert` and `delete` is bounded by 2 log n
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Color {
+    Red,
+    Black,
+}
+
+impl Color {
+    fn flip(self) -> Self {
+        return match self { Color::Red => Color::Black, Color::Black => Color::Red };
+    }
+}
+
+type Link<K, D> = Option<Box<RBNode<K, D>>>;
+
+#[derive(Clone)]
+pub struct RBNode<K, D> {
+    key: K,
+    data: D,
+    // the color of the link from this node's parent
+    color: Color,
+    left: Link<K, D>,
+    right: Link<K, D>,
+}
+
+fn is_red<K, D>(link: &Link<K, D>) -> bool {
+    return link.as_ref().is_some_and(|node| node.color == Color::Red);
+}
+
+fn is_red_left<K, D>(link: &Link<K, D>) -> bool {
+    return link.as_ref().is_some_and(|node| is_red(&node.left));
+}
+
+impl<K, D> RBNode<K, D> {
+    fn rotate_left(mut node: Box<Self>) -> Box<Self> {
+        let mut right = node.right.take().expect("no right child");
+        node.right = right.left.take();
+        right.color = node.color;
+        node.color = Color::Red;
+        right.left = Some(node);
+        return right;
+    }
+
+    fn rotate_right(mut node: Box<Self>) -> Box<Self> {
+        let mut left = node.left.take().expect("no left child");
+        node.left = left.right.take();
+        left.color = node.color;
+        node.color = Color::Red;
+        left.right = Some(node);
+        return left;
+    }
+
+    /// flip the colors of a node and both its children, splitting or joining a 4-node
+    fn flip_colors(&mut self) {
+        self.color = self.color.flip();
+        for child in [self.left.as_mut(), self.right.as_mut()].iter_mut().flatten() {
+            child.color = child.color.flip();
+        }
+    }
+
+    /// restore the left-leaning invariants on the way back up from a change below
+    fn fix_up(mut node: Box<Self>) -> Box<Self> {
+        if is_red(&node.right) && !is_red(&node.left) { node = Self::rotate_left(node) }
+        if is_red(&node.left) && is_red_left(&node.left) { node = Self::rotate_right(node) }
+        if is_red(&node.left) && is_red(&node.right) { node.flip_colors() }
+        return node;
+    }
+
+    /// make sure the left child or one of its children is red, so a node can be removed
+    /// from the left subtree without leaving it a black link short
+    fn move_red_left(mut node: Box<Self>) -> Box<Self> {
+        node.flip_colors();
+        if is_red_left(&node.right) {
+            node.right = node.right.take().map(Self::rotate_right);
+            node = Self::rotate_left(node);
+            node.flip_colors();
+        }
+        return node;
+    }
+
+    fn move_red_right(mut node: Box<Self>) -> Box<Self> {
+        node.flip_colors();
+        if is_red_left(&node.left) {
+            node = Self::rotate_right(node);
+            node.flip_colors();
+        }
+        return node;
+    }
+
+    /// insert or replace a key, returning the new subtree root and any replaced data
+    fn insert<C: Compare<K>>(link: Link<K, D>, key: K, data: D, cmp: &C) -> (Box<Self>, Option<D>) {
+        let Some(mut node) = link else {
+            return (Box::new(RBNode { key, data, color: Color::Red, left: None, right: None }), None);
+        };
+        let old = match cmp.compare(&key, &node.key) {
+            Less => {
+                let (left, old) = Self::insert(node.left.take(), key, data, cmp);
+                node.left = Some(left);
+                old
+            }
+            Greater => {
+                let (right, old) = Self::insert(node.right.take(), key, data, cmp);
+                node.right = Some(right);
+                old
+            }
+            Equal => {
+                node.key = key;
+                Some(mem::replace(&mut node.data, data))
+            }
+        };
+        return (Self::fix_up(node), old);
+    }
+
+    /// detach the node with the smallest key, returning what is left and the node
+    fn delete_min(mut node: Box<Self>) -> (Link<K, D>, Box<Self>) {
+        if node.left.is_none() {
+            // left-leaning, so a node with no left child has no right child either
+            return (None, node);
+        }
+        if !is_red(&node.left) && !is_red_left(&node.left) { node = Self::move_red_left(node) }
+        let (left, min) = Self::delete_min(node.left.take().expect("no left child"));
+        node.left = left;
+        return (Some(Self::fix_up(node)), min);
+    }
+
+    /// remove a key that is known to be in the subtree, returning what is left and a node
+    /// holding the removed key and data
+    fn delete<Q, C>(mut node: Box<Self>, key: &Q, cmp: &C) -> (Link<K, D>, Box<Self>)
+    where K: Borrow<Q>, Q: ?Sized, C: Compare<Q>
+    {
+        let removed;
+        if cmp.compare(key, node.key.borrow()) == Less {
+            if !is_red(&node.left) && !is_red_left(&node.left) { node = Self::move_red_left(node) }
+            let (left, found) = Self::delete(node.left.take().expect("key not in subtree"), key, cmp);
+            node.left = left;
+            removed = found;
+        } else {
+            if is_red(&node.left) { node = Self::rotate_right(node) }
+            if cmp.compare(key, node.key.borrow()) == Equal && node.right.is_none() {
+                return (None, node);
+            }
+            if !is_red(&node.right) && !is_red_left(&node.right) { node = Self::move_red_right(node) }
+            let right = node.right.take().expect("key not in subtree");
+            if cmp.compare(key, node.key.borrow()) == Equal {
+                // take the successor's place, and hand back the successor holding our pair
+                let (right, mut successor) = Self::delete_min(right);
+                mem::swap(&mut node.key, &mut successor.key);
+                mem::swap(&mut node.data, &mut successor.data);
+                node.right = right;
+                removed = successor;
+            } else {
+                let (right, found) = Self::delete(right, key, cmp);
+                node.right = right;
+                removed = found;
+            }
+        }
+        return (Some(Self::fix_up(node)), removed);
+    }
+
+    fn get<Q, C>(&self, key: &Q, cmp: &C) -> Option<&Self>
+    where K: Borrow<Q>, Q: ?Sized, C: Compare<Q>
+    {
+        let mut curr = Some(self);
+        while let Some(node) = curr {
+            curr = match cmp.compare(key, node.key.borrow()) {
+                Less => node.left.as_deref(),
+                Greater => node.right.as_deref(),
+                Equal => return Some(node)
+            };
+        }
+        return None;
+    }
+
+    /// check the left-leaning red-black invariants below this node, returning its black
+    /// height, the number of black links on every path down to an empty subtree, and the
+    /// number of nodes in its subtree
+    fn validate<C: Compare<K>>(&self, cmp: &C, lower: Option<&K>, upper: Option<&K>) -> Result<(usize, usize), Error>
+    where K: fmt::Debug
+    {
+        if lower.is_some_and(|lower| cmp.compare(lower, &self.key) != Less)
+            || upper.is_some_and(|upper| cmp.compare(&self.key, upper) != Less) {
+            return Err(Error::InvariantViolation(format!("key {:?} is out of order", self.key)));
+        }
+        if self.color == Color::Red && (is_red(&self.left) || is_red(&self.right)) {
+            return Err(Error::InvariantViolation(format!("red node {:?} has a red child", self.key)));
+        }
+        // deletes rely on red links leaning left, so that a node with no left child is a leaf
+        if is_red(&self.right) {
+            return Err(Error::InvariantViolation(format!("red link leans right below {:?}", self.key)));
+        }
+        let (left, left_count) = self.left.as_ref().map_or(Ok((0, 0)), |left| left.validate(cmp, lower, Some(&self.key)))?;
+        let (right, right_count) = self.right.as_ref().map_or(Ok((0, 0)), |right| right.validate(cmp, Some(&self.key), upper))?;
+        if left != right {
+            return Err(Error::InvariantViolation(
+                format!("black heights {} and {} differ below {:?}", left, right, self.key)));
+        }
+        return Ok((left + (self.color == Color::Black) as usize, left_count + right_count + 1));
+    }
+
+    fn height(&self) -> usize {
+        let left = self.left.as_ref().map_or(0, |left| left.height());
+        let right = self.right.as_ref().map_or(0, |right| right.height());
+        return left.max(right) + 1;
+    }
+}
+
+/// a map ordered by `C`, which defaults to the keys' `Ord` implementation, kept balanced as
+/// a red-black tree. it has the same interface as `AVLTree`, whose stricter balance makes
+/// lookups a little faster at the cost of more rotations on writes
+pub struct RBTree<K, D, C = Natural> {
+    root: Link<K, D>,
+    cmp: C,
+    len: usize,
+}
+
+impl<K, D> RBTree<K, D>
+where K: Ord + Eq + Clone + fmt::Debug, D: Clone + fmt::Debug
+{
+    pub fn new() -> Self {
+        return Self::with_comparator(Natural);
+    }
+}
+
+impl<K, D, C> RBTree<K, D, C> {
+    pub fn iter(&self) -> RBIter<'_, K, D> {
+        return Walk::new(self.root.as_deref(), IterType::DFInOrder);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.len;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len == 0;
+    }
+
+    /// remove every key/data pair
+    pub fn clear(&mut self) {
+        self.root = None;
+        self.len = 0;
+    }
+
+    /// the comparator that orders this tree's keys
+    pub fn comparator(&self) -> &C {
+        return &self.cmp;
+    }
+
+    /// number of nodes on the longest path from the root down, which is never more than
+    /// twice the shortest
+    pub fn height(&self) -> usize {
+        return self.root.as_ref().map_or(0, |root| root.height());
+    }
+}
+
+impl<K, D, C> RBTree<K, D, C>
+where K: Clone + fmt::Debug, D: Clone + fmt::Debug, C: Compare<K>
+{
+    /// create an empty tree ordered by `cmp`
+    pub fn with_comparator(cmp: C) -> Self {
+        return Self { root: None, cmp, len: 0 };
+    }
+
+    /// insert a new key/data pair into the tree
+    /// if the key was already present, its data is replaced and the old data returned
+    pub fn put(&mut self, key: K, data: D) -> Option<D> {
+        let (mut root, old) = RBNode::insert(self.root.take(), key, data, &self.cmp);
+        root.color = Color::Black;
+        self.root = Some(root);
+        if old.is_none() { self.len += 1 }
+        return old;
+    }
+
+    /// get a copy of the data associated with a given key
+    pub fn get(&self, key: K) -> Option<D> {
+        return self.find(&key).map(|node| node.data.clone());
+    }
+
+    /// iterate in order over the key/data pairs whose keys fall within `range`
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> RBRange<'_, K, D, C> {
+        return Span::new(self.root.as_deref(), range, &self.cmp);
+    }
+
+    /// delete the node specified by key and return its data
+    pub fn del(&mut self, key: K) -> Result<D, Error> {
+        // the delete transformations assume the key is there, so look before leaping
+        if self.find(&key).is_none() { return Err(Error::KeyNotFound) }
+        let mut root = self.root.take().expect("found a key in an empty tree");
+        if !is_red(&root.left) && !is_red(&root.right) { root.color = Color::Red }
+        let (mut root, removed) = RBNode::delete(root, &key, &self.cmp);
+        if let Some(root) = root.as_mut() { root.color = Color::Black }
+        self.root = root;
+        self.len -= 1;
+        return Ok(removed.data);
+    }
+
+    /// check the left-leaning red-black invariants: keys are in `cmp` order, the root is
+    /// black, no red node has a red child, no red node is a right child and every path from
+    /// the root down passes the same number of black nodes. also checks that `len` counts
+    /// the nodes
+    pub fn validate(&self) -> Result<(), Error> {
+        let count = match self.root.as_ref() {
+            Some(root) if root.color == Color::Red => {
+                return Err(Error::InvariantViolation(format!("root {:?} is red", root.key)));
+            }
+            Some(root) => root.validate(&self.cmp, None, None)?.1,
+            None => 0
+        };
+        if count != self.len {
+            return Err(Error::InvariantViolation(format!("{} nodes but a length of {}", count, self.len)));
+        }
+        return Ok(());
+    }
+
+    /// return a vector of cloned key/value tuples
+    pub fn items(&self) -> Vec<(K,D)> {
+        return self.iter().map(|(key, data)| (key.clone(), data.clone())).collect();
+    }
+
+    fn find<Q>(&self, key: &Q) -> Option<&RBNode<K, D>>
+    where K: Borrow<Q>, Q: ?Sized, C: Compare<Q>
+    {
+        return self.root.as_ref()?.get(key, &self.cmp);
+    }
+}
+
+/// iterates over a red-black tree's key/data pairs in any of the `IterType` orders,
+/// in order by default
+pub type RBIter<'a, K, D> = Walk<'a, RBNode<K, D>>;
+
+/// owning in-order iterator over a red-black tree's key/data pairs
+pub type RBIntoIter<K, D> = Drain<RBNode<K, D>>;
+
+/// iterates in order over the pairs of a red-black tree whose keys fall within a range
+pub type RBRange<'a, K, D, C = Natural> = Span<'a, RBNode<K, D>, C>;
+
+impl<K, D> Branch for RBNode<K, D> {
+    type Key = K;
+    type Data = D;
+
+    fn pair(&self) -> (&K, &D) {
+        return (&self.key, &self.data);
+    }
+
+    fn left(&self) -> Option<&Self> {
+        return self.left.as_deref();
+    }
+
+    fn right(&self) -> Option<&Self> {
+        return self.right.as_deref();
+    }
+
+    fn unlink(self: Box<Self>) -> ((K, D), Link<K, D>, Link<K, D>) {
+        let RBNode { key, data, left, right, .. } = *self;
+        return ((key, data), left, right);
+    }
+}
+
+impl<'a, K, D, C> IntoIterator for &'a RBTree<K, D, C> {
+    type Item = (&'a K, &'a D);
+    type IntoIter = RBIter<'a, K, D>;
+
+    fn into_iter(self) -> RBIter<'a, K, D> {
+        return self.iter();
+    }
+}
+
+impl<K, D, C> IntoIterator for RBTree<K, D, C> {
+    type Item = (K, D);
+    type IntoIter = RBIntoIter<K, D>;
+
+    fn into_iter(self) -> RBIntoIter<K, D> {
+        return Drain::new(self.root);
+    }
+}
+
+impl<K, D> From<BTreeMap<K,D>> for RBTree<K, D>
+where K: Ord + Eq + Clone + fmt::Debug, D: Clone + fmt::Debug
+{
+    fn from(nodes: BTreeMap<K,D>) -> RBTree<K, D> {
+        return nodes.into_iter().collect();
+    }
+}
+
+impl<K: Clone, D: Clone, C: Clone> Clone for RBTree<K, D, C> {
+    fn clone(&self) -> Self {
+        return Self { root: self.root.clone(), cmp: self.cmp.clone(), len: self.len };
+    }
+}
+
+impl<K, D, C: Default> Default for RBTree<K, D, C> {
+    fn default() -> Self {
+        return Self { root: None, cmp: C::default(), len: 0 };
+    }
+}
+
+map_traits!(RBTree);
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AVLTree;
+    use std::collections::HashMap;
+    use core::cmp::Ordering;
+    use core::hash::{Hash, Hasher};
+    use core::ops::Bound;
+
+    fn test_put_set<K,D> (xs: HashMap<K, D>)
+        where K: Ord + Eq + Clone + fmt::Debug,
+              D: Ord + Eq + Clone + fmt::Debug
+    {
+        let mut vec: Vec<(K,D)> = xs.iter().map(|(x,y)| (x.clone(),y.clone())).collect();
+        let tree = RBTree::from(&vec);
+        vec.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(tree.items(), vec);
+        assert_eq!(tree.validate(), Ok(()));
+    }
+
+    #[quickcheck]
+    fn qc_test_put_set_isize_isize (xs: HashMap<isize, isize>) {
+        test_put_set(xs);
+    }
+
+    #[quickcheck]
+    fn qc_test_put_set_isize_string (xs: HashMap<isize, String>) {
+        test_put_set(xs);
+    }
+
+    #[quickcheck]
+    fn qc_test_put_set_string_string (xs: HashMap<String, String>) {
+        test_put_set(xs);
+    }
+
+    #[cfg(feature = "std")]
+    #[quickcheck]
+    fn qc_test_put_duplicate(xs: HashMap<isize, isize>, key: isize) {
+        let mut tree = RBTree::from(&xs);
+        let mut expected = xs.clone();
+        assert_eq!(tree.put(key, 0), expected.insert(key, 0));
+
+        let mut vec: Vec<(isize,isize)> = expected.into_iter().collect();
+        vec.sort();
+        assert_eq!(tree.items(), vec);
+        assert_eq!(tree.validate(), Ok(()));
+    }
+
+    #[cfg(feature = "std")]
+    #[quickcheck]
+    fn qc_test_del_missing(xs: HashMap<isize, isize>, key: isize) {
+        if xs.contains_key(&key) { return }
+        let mut tree = RBTree::from(&xs);
+        let before = tree.items();
+        assert_eq!(tree.del(key), Err(Error::KeyNotFound));
+        assert_eq!(tree.items(), before);
+    }
+
+    /// the tree stays valid and matches a `BTreeMap` through any mix of puts and deletes
+    #[quickcheck]
+    fn qc_test_ops(ops: Vec<(bool, u8, u8)>) {
+        let mut tree = RBTree::new();
+        let mut model = BTreeMap::new();
+        for (put, key, data) in ops {
+            if put {
+                assert_eq!(tree.put(key, data), model.insert(key, data));
+            } else {
+                assert_eq!(tree.del(key).ok(), model.remove(&key));
+            }
+            assert_eq!(tree.validate(), Ok(()));
+            assert_eq!(tree.len(), model.len());
+        }
+        assert_eq!(BTreeMap::from(tree), model);
+    }
+
+    #[quickcheck]
+    fn qc_test_range(xs: BTreeMap<u8, u8>, from: u8, to: u8) {
+        let tree = RBTree::from(xs.clone());
+        let (from, to) = (from.min(to), from.max(to));
+        assert!(tree.range(from..to).eq(xs.range(from..to)));
+        assert!(tree.range((Bound::Excluded(from), Bound::Included(to)))
+            .eq(xs.range((Bound::Excluded(from), Bound::Included(to)))));
+        assert!(tree.range(..=from).eq(xs.range(..=from)));
+        assert!(tree.range(to..).eq(xs.range(to..)));
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut tree = RBTree::new();
+        for i in 0..1000 {
+            tree.put(i, i);
+            assert_eq!(tree.validate(), Ok(()));
+        }
+        // at most twice as tall as a perfectly balanced tree
+        assert!(tree.height() <= 2 * 10);
+        for i in (0..1000).step_by(3) {
+            assert_eq!(tree.del(i), Ok(i));
+            assert_eq!(tree.validate(), Ok(()));
+        }
+
+        let leaf = |key, color| Some(Box::new(RBNode { key, data: (), color, left: None, right: None }));
+        let tree = |root: Link<u8, ()>| {
+            let mut tree = RBTree { root, cmp: Natural, len: 0 };
+            tree.len = tree.iter().count();
+            tree
+        };
+        let node = |key, color, left, right| Some(Box::new(RBNode { key, data: (), color, left, right }));
+
+        // a red root
+        assert!(matches!(tree(leaf(1, Color::Red)).validate(), Err(Error::InvariantViolation(_))));
+        // a red node with a red child
+        let red_red = node(2, Color::Black, node(1, Color::Red, leaf(0, Color::Red), None), None);
+        assert!(matches!(tree(red_red).validate(), Err(Error::InvariantViolation(_))));
+        // one more black node on the left than on the right
+        let unbalanced = node(1, Color::Black, leaf(0, Color::Black), None);
+        assert!(matches!(tree(unbalanced).validate(), Err(Error::InvariantViolation(_))));
+        // keys out of order
+        let unordered = node(1, Color::Black, leaf(2, Color::Red), None);
+        assert!(matches!(tree(unordered).validate(), Err(Error::InvariantViolation(_))));
+        // a red right child, balanced and in order but leaning the wrong way
+        let right_red = node(1, Color::Black, None, leaf(2, Color::Red));
+        assert!(matches!(tree(right_red).validate(), Err(Error::InvariantViolation(_))));
+        // a valid tree whose length is off by one
+        let mut miscounted = tree(node(1, Color::Black, leaf(0, Color::Red), None));
+        assert_eq!(miscounted.validate(), Ok(()));
+        miscounted.len = 1;
+        assert!(matches!(miscounted.validate(), Err(Error::InvariantViolation(_))));
+        miscounted.len = 0;
+        assert!(matches!(miscounted.validate(), Err(Error::InvariantViolation(_))));
+        assert!(matches!(tree(None).validate(), Ok(())));
+    }
+
+    #[test]
+    fn test_iter_types() {
+        // insertion order chosen so the tree comes out as
+        //        4
+        //      2   6
+        //     1 3 5 7
+        let tree = RBTree::from([(4, ()), (2, ()), (6, ()), (1, ()), (3, ()), (5, ()), (7, ())]);
+        let keys = |itype| tree.iter().with_type(itype).map(|(key, _)| *key).collect::<Vec<_>>();
+        assert_eq!(keys(IterType::DFInOrder), vec![1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(keys(IterType::DFInOrderReverse), vec![7, 6, 5, 4, 3, 2, 1]);
+        assert_eq!(keys(IterType::DFPreOrder), vec![4, 2, 1, 3, 6, 5, 7]);
+        assert_eq!(keys(IterType::DFPostOrder), vec![1, 3, 2, 5, 7, 6, 4]);
+        assert_eq!(keys(IterType::BF), vec![4, 2, 6, 1, 3, 5, 7]);
+        assert_eq!(RBTree::<u8, ()>::new().iter().with_type(IterType::DFPostOrder).next(), None);
+    }
+
+    #[quickcheck]
+    fn qc_test_reverse_comparator(xs: HashMap<isize, isize>) {
+        let mut tree = RBTree::with_comparator(|a: &isize, b: &isize| b.cmp(a));
+        for (k, d) in xs.iter() {
+            tree.put(*k, *d);
+        }
+        assert_eq!(tree.validate(), Ok(()));
+
+        let mut vec: Vec<(isize,isize)> = xs.into_iter().collect();
+        vec.sort_by_key(|&(k, _)| std::cmp::Reverse(k));
+        assert_eq!(tree.items(), vec);
+
+        for (k, d) in vec {
+            assert_eq!(tree.get(k), Some(d));
+            assert_eq!(tree.del(k), Ok(d));
+            assert_eq!(tree.validate(), Ok(()));
+        }
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn test_case_insensitive_comparator() {
+        let mut tree = RBTree::with_comparator(|a: &String, b: &String| a.to_lowercase().cmp(&b.to_lowercase()));
+        tree.put(String::from("banana"), 1);
+        tree.put(String::from("Apple"), 2);
+        tree.put(String::from("cherry"), 3);
+        assert_eq!(tree.put(String::from("APPLE"), 4), Some(2));
+
+        assert_eq!(tree.get(String::from("apple")), Some(4));
+        let keys: Vec<&String> = tree.iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec!["APPLE", "banana", "cherry"]);
+        assert_eq!(tree.del(String::from("Banana")), Ok(1));
+        assert_eq!(tree.get(String::from("banana")), None);
+    }
+
+    #[quickcheck]
+    fn qc_test_same_as_avl(xs: Vec<(isize, isize)>) {
+        let rb = RBTree::from(&xs);
+        let avl = AVLTree::from(&xs);
+        assert_eq!(rb.items(), avl.items());
+        assert_eq!(rb.len(), avl.len());
+    }
+
+    #[quickcheck]
+    fn qc_test_eq_ignores_shape(xs: HashMap<isize, isize>) {
+        let vec: Vec<(isize,isize)> = xs.into_iter().collect();
+        let forward: RBTree<isize,isize> = vec.iter().cloned().collect();
+        let mut backward = RBTree::new();
+        backward.extend(vec.iter().rev().map(|(k, d)| (k, d)));
+
+        assert_eq!(forward, backward);
+        assert_eq!(forward.cmp(&backward), Ordering::Equal);
+
+        let hash = |tree: &RBTree<isize,isize>| {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            tree.hash(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash(&forward), hash(&backward));
+
+        let cloned = forward.clone();
+        assert_eq!(cloned, forward);
+        assert_eq!(cloned.validate(), Ok(()));
+    }
+
+    #[quickcheck]
+    fn qc_test_btreemap_roundtrip(xs: BTreeMap<isize, String>) {
+        let tree = RBTree::from(xs.clone());
+        assert_eq!(tree.validate(), Ok(()));
+        for (k, d) in xs.iter() {
+            assert_eq!(&tree[k], d);
+        }
+        let back: BTreeMap<isize, String> = tree.into();
+        assert_eq!(back, xs);
+    }
+
+    #[test]
+    fn test_std_traits() {
+        let tree = RBTree::from([(3, "c"), (1, "a"), (2, "b")]);
+        assert_eq!(tree.items(), vec![(1, "a"), (2, "b"), (3, "c")]);
+        assert_eq!(tree[&2], "b");
+        assert_eq!(format!("{:?}", tree), r#"{1: "a", 2: "b", 3: "c"}"#);
+
+        let empty: RBTree<String, isize> = RBTree::default();
+        assert!(empty.is_empty());
+
+        // borrowed lookups: String keys indexed by &str
+        let mut names: RBTree<String, usize> = RBTree::default();
+        names.extend(vec![(String::from("one"), 1), (String::from("two"), 2)]);
+        assert_eq!(names["two"], 2);
+
+        let owned: Vec<(String, usize)> = names.into_iter().collect();
+        assert_eq!(owned, vec![(String::from("one"), 1), (String::from("two"), 2)]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_index_missing() {
+        let tree = RBTree::from([(1, 1)]);
+        let _ = tree[&2];
+    }
+
+    #[quickcheck]
+    fn qc_test_len(puts: Vec<(u8, u8)>, dels: Vec<u8>) {
+        let mut tree = RBTree::new();
+        let mut model = BTreeMap::new();
+        for (k, d) in puts {
+            tree.put(k, d);
+            model.insert(k, d);
+            assert_eq!(tree.len(), model.len());
+        }
+        for k in dels {
+            assert_eq!(tree.del(k).ok(), model.remove(&k));
+            assert_eq!(tree.len(), model.len());
+        }
+        assert_eq!(tree.is_empty(), model.is_empty());
+
+        tree.clear();
+        assert_eq!(tree.len(), 0);
+        assert!(tree.iter().next().is_none());
+    }
+}
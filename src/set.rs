@@ -0,0 +1,312 @@
+use crate::{AVLTree, NodeIter, Range};
+use core::cmp::Ordering::{Equal, Greater, Less};
+use core::fmt;
+use core::iter::{FromIterator, Peekable};
+use core::ops::RangeBounds;
+use alloc::vec::Vec;
+
+/// an ordered set of keys, stored in the same nodes as `AVLTree` with no data attached
+pub struct AVLSet<K> {
+    tree: AVLTree<K, ()>
+}
+
+impl<K> AVLSet<K>
+where K: Ord + Eq + Clone + fmt::Debug
+{
+    pub fn new() -> Self {
+        Self { tree: AVLTree::new() }
+    }
+
+    /// add a key to the set. returns false if it was already present
+    pub fn insert(&mut self, key: K) -> bool {
+        return self.tree.put(key, ()).is_none();
+    }
+
+    /// remove a key from the set. returns false if it was not present
+    pub fn remove(&mut self, key: &K) -> bool {
+        return self.tree.remove_node(key).is_ok();
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        return self.tree.find(key).is_some();
+    }
+
+    /// number of keys in the set, in O(1)
+    pub fn len(&self) -> usize {
+        return self.tree.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.tree.is_empty();
+    }
+
+    /// the smallest key in the set
+    pub fn first(&self) -> Option<&K> {
+        return self.tree.first_node().map(|node| &node.key);
+    }
+
+    /// the largest key in the set
+    pub fn last(&self) -> Option<&K> {
+        return self.tree.last_node().map(|node| &node.key);
+    }
+
+    /// iterate over the keys in order
+    pub fn iter(&self) -> SetIter<'_, K> {
+        return SetIter { inner: self.tree.iter() };
+    }
+
+    /// iterate in order over the keys that fall within `range`
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> SetRange<'_, K> {
+        return SetRange { inner: self.tree.range(range) };
+    }
+
+    /// keys in either set, in order
+    pub fn union<'a>(&'a self, other: &'a Self) -> Union<'a, K> {
+        return Union { a: self.iter().peekable(), b: other.iter().peekable() };
+    }
+
+    /// keys in both sets, in order
+    pub fn intersection<'a>(&'a self, other: &'a Self) -> Intersection<'a, K> {
+        return Intersection { a: self.iter().peekable(), b: other.iter().peekable() };
+    }
+
+    /// keys in this set but not in `other`, in order
+    pub fn difference<'a>(&'a self, other: &'a Self) -> Difference<'a, K> {
+        return Difference { a: self.iter().peekable(), b: other.iter().peekable() };
+    }
+
+    /// keys in exactly one of the two sets, in order
+    pub fn symmetric_difference<'a>(&'a self, other: &'a Self) -> SymmetricDifference<'a, K> {
+        return SymmetricDifference { a: self.iter().peekable(), b: other.iter().peekable() };
+    }
+
+    /// true if every key in this set is also in `other`
+    pub fn is_subset(&self, other: &Self) -> bool {
+        return self.difference(other).next().is_none();
+    }
+
+    /// true if every key in `other` is also in this set
+    pub fn is_superset(&self, other: &Self) -> bool {
+        return other.is_subset(self);
+    }
+
+    /// true if the two sets have no keys in common
+    pub fn is_disjoint(&self, other: &Self) -> bool {
+        return self.intersection(other).next().is_none();
+    }
+}
+
+impl<K> Default for AVLSet<K>
+where K: Ord + Eq + Clone + fmt::Debug
+{
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl<K> FromIterator<K> for AVLSet<K>
+where K: Ord + Eq + Clone + fmt::Debug
+{
+    fn from_iter<I: IntoIterator<Item = K>>(iter: I) -> Self {
+        let mut keys: Vec<K> = iter.into_iter().collect();
+        keys.sort();
+        keys.dedup();
+        return Self { tree: AVLTree::from_sorted_iter(keys.into_iter().map(|key| (key, ()))) };
+    }
+}
+
+impl<'a, K> IntoIterator for &'a AVLSet<K>
+where K: Ord + Eq + Clone + fmt::Debug
+{
+    type Item = &'a K;
+    type IntoIter = SetIter<'a, K>;
+
+    fn into_iter(self) -> SetIter<'a, K> {
+        return self.iter();
+    }
+}
+
+impl<K> fmt::Debug for AVLSet<K>
+where K: Ord + Eq + Clone + fmt::Debug
+{
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        return formatter.debug_set().entries(self.iter()).finish();
+    }
+}
+
+/// in-order iterator over the keys of an `AVLSet`
+pub struct SetIter<'a, K> {
+    inner: NodeIter<'a, K, ()>
+}
+
+impl<'a, K: Ord + Eq> Iterator for SetIter<'a, K> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<&'a K> {
+        return self.inner.next().map(|(key, _)| key);
+    }
+}
+
+/// in-order iterator over the keys of an `AVLSet` within a range
+pub struct SetRange<'a, K> {
+    inner: Range<'a, K, ()>
+}
+
+impl<'a, K: Ord> Iterator for SetRange<'a, K> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<&'a K> {
+        return self.inner.next().map(|(key, _)| key);
+    }
+}
+
+/* each set operation merge-walks the two sets in order, so it runs in O(n + m) and only
+ * does as much work as the caller consumes
+ */
+
+pub struct Union<'a, K: Ord> {
+    a: Peekable<SetIter<'a, K>>,
+    b: Peekable<SetIter<'a, K>>
+}
+
+impl<'a, K: Ord> Iterator for Union<'a, K> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<&'a K> {
+        match (self.a.peek(), self.b.peek()) {
+            (Some(a), Some(b)) => match a.cmp(b) {
+                Less => self.a.next(),
+                Greater => self.b.next(),
+                Equal => {
+                    self.b.next();
+                    self.a.next()
+                }
+            },
+            (Some(_), None) => self.a.next(),
+            (None, _) => self.b.next()
+        }
+    }
+}
+
+pub struct Intersection<'a, K: Ord> {
+    a: Peekable<SetIter<'a, K>>,
+    b: Peekable<SetIter<'a, K>>
+}
+
+impl<'a, K: Ord> Iterator for Intersection<'a, K> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<&'a K> {
+        loop {
+            match self.a.peek()?.cmp(self.b.peek()?) {
+                Less => { self.a.next(); }
+                Greater => { self.b.next(); }
+                Equal => {
+                    self.b.next();
+                    return self.a.next();
+                }
+            }
+        }
+    }
+}
+
+pub struct Difference<'a, K: Ord> {
+    a: Peekable<SetIter<'a, K>>,
+    b: Peekable<SetIter<'a, K>>
+}
+
+impl<'a, K: Ord> Iterator for Difference<'a, K> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<&'a K> {
+        loop {
+            let a = self.a.peek()?;
+            match self.b.peek().map(|b| a.cmp(b)) {
+                None | Some(Less) => return self.a.next(),
+                Some(Greater) => { self.b.next(); }
+                Some(Equal) => {
+                    self.a.next();
+                    self.b.next();
+                }
+            }
+        }
+    }
+}
+
+pub struct SymmetricDifference<'a, K: Ord> {
+    a: Peekable<SetIter<'a, K>>,
+    b: Peekable<SetIter<'a, K>>
+}
+
+impl<'a, K: Ord> Iterator for SymmetricDifference<'a, K> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<&'a K> {
+        loop {
+            match (self.a.peek(), self.b.peek()) {
+                (Some(a), Some(b)) => match a.cmp(b) {
+                    Less => return self.a.next(),
+                    Greater => return self.b.next(),
+                    Equal => {
+                        self.a.next();
+                        self.b.next();
+                    }
+                },
+                (Some(_), None) => return self.a.next(),
+                (None, _) => return self.b.next()
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    #[quickcheck]
+    fn qc_test_insert_remove(xs: Vec<isize>, ys: Vec<isize>) {
+        let mut set = AVLSet::new();
+        let mut model = BTreeSet::new();
+        for x in xs {
+            assert_eq!(set.insert(x), model.insert(x));
+        }
+        for y in ys {
+            assert_eq!(set.remove(&y), model.remove(&y));
+            assert_eq!(set.contains(&y), model.contains(&y));
+        }
+        assert!(set.iter().eq(model.iter()));
+        assert_eq!(set.len(), model.len());
+        assert_eq!(set.first(), model.iter().next());
+        assert_eq!(set.last(), model.iter().next_back());
+    }
+
+    #[quickcheck]
+    fn qc_test_range(xs: Vec<isize>, a: isize, b: isize) {
+        let (lo, hi) = if a <= b { (a, b) } else { (b, a) };
+        let set: AVLSet<isize> = xs.iter().cloned().collect();
+        let model: BTreeSet<isize> = xs.into_iter().collect();
+
+        assert!(set.range(lo..hi).eq(model.range(lo..hi)));
+        assert!(set.range(lo..=hi).eq(model.range(lo..=hi)));
+        assert!(set.range(..hi).eq(model.range(..hi)));
+        assert!(set.range(lo..).eq(model.range(lo..)));
+    }
+
+    #[quickcheck]
+    fn qc_test_set_algebra(xs: Vec<u8>, ys: Vec<u8>) {
+        let a: AVLSet<u8> = xs.iter().cloned().collect();
+        let b: AVLSet<u8> = ys.iter().cloned().collect();
+        let ma: BTreeSet<u8> = xs.into_iter().collect();
+        let mb: BTreeSet<u8> = ys.into_iter().collect();
+
+        assert!(a.union(&b).eq(ma.union(&mb)));
+        assert!(a.intersection(&b).eq(ma.intersection(&mb)));
+        assert!(a.difference(&b).eq(ma.difference(&mb)));
+        assert!(a.symmetric_difference(&b).eq(ma.symmetric_difference(&mb)));
+        assert_eq!(a.is_subset(&b), ma.is_subset(&mb));
+        assert_eq!(a.is_superset(&b), ma.is_superset(&mb));
+        assert_eq!(a.is_disjoint(&b), ma.is_disjoint(&mb));
+    }
+}
@@ -0,0 +1,639 @@
+use crate::{Compare, Error, IterType, Natural};
+use crate::walk::{map_traits, Branch, Drain, Span, Walk};
+use core::borrow::Borrow;
+use core::cmp::Ordering::{Equal, Greater, Less};
+use core::fmt;
+use core::iter::FromIterator;
+use core::mem;
+use core::ops::RangeBounds;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::vec::Vec;
+
+/* a splay tree keeps no balance information at all. every access moves the key it looks
+ * for, or the last node on the way to it, up to the root, rotating pairs of nodes so that
+ * the path it came along is roughly halved. single operations can take O(n), but any
+ * sequence of them costs O(log n) amortised each, and keys that are used often stay near
+ * the root
+ */
+
+type Link<K, D> = Option<Box<SplayNode<K, D>>>;
+
+pub struct SplayNode<K, D> {
+    key: K,
+    data: D,
+    left: Link<K, D>,
+    right: Link<K, D>,
+}
+
+impl<K, D> SplayNode<K, D> {
+    fn new(key: K, data: D) -> Box<Self> {
+        return Box::new(SplayNode { key, data, left: None, right: None });
+    }
+
+    /// top-down splay: bring the node holding `key` to the root, or if there is none, the
+    /// last node visited looking for it, which holds its predecessor or successor.
+    ///
+    /// on the way down, nodes known to be smaller than `key` are hung off the right end of
+    /// a left tree and larger ones off the left end of a right tree. at the bottom both
+    /// trees are reassembled under the new root
+    fn splay<Q, C>(mut root: Box<Self>, key: &Q, cmp: &C) -> Box<Self>
+    where K: Borrow<Q>, Q: ?Sized, C: Compare<Q>
+    {
+        // the left tree's nodes in increasing order, and the right tree's decreasing; each
+        // is the parent of the next, as its right or left child respectively
+        let mut smaller: Vec<Box<Self>> = Vec::new();
+        let mut larger: Vec<Box<Self>> = Vec::new();
+        loop {
+            match cmp.compare(key, root.key.borrow()) {
+                Less => {
+                    let Some(mut left) = root.left.take() else { break };
+                    if cmp.compare(key, left.key.borrow()) == Less {
+                        // zig-zig: rotate right before linking
+                        root.left = left.right.take();
+                        left.right = Some(root);
+                        root = left;
+                        let Some(next) = root.left.take() else { break };
+                        larger.push(root);
+                        root = next;
+                    } else {
+                        larger.push(root);
+                        root = left;
+                    }
+                }
+                Greater => {
+                    let Some(mut right) = root.right.take() else { break };
+                    if cmp.compare(key, right.key.borrow()) == Greater {
+                        // zag-zag: rotate left before linking
+                        root.right = right.left.take();
+                        right.left = Some(root);
+                        root = right;
+                        let Some(next) = root.right.take() else { break };
+                        smaller.push(root);
+                        root = next;
+                    } else {
+                        smaller.push(root);
+                        root = right;
+                    }
+                }
+                Equal => break
+            }
+        }
+        let mut left = root.left.take();
+        for mut node in smaller.into_iter().rev() {
+            node.right = left;
+            left = Some(node);
+        }
+        let mut right = root.right.take();
+        for mut node in larger.into_iter().rev() {
+            node.left = right;
+            right = Some(node);
+        }
+        root.left = left;
+        root.right = right;
+        return root;
+    }
+
+    fn get<Q, C>(&self, key: &Q, cmp: &C) -> Option<&Self>
+    where K: Borrow<Q>, Q: ?Sized, C: Compare<Q>
+    {
+        let mut curr = Some(self);
+        while let Some(node) = curr {
+            curr = match cmp.compare(key, node.key.borrow()) {
+                Less => node.left.as_deref(),
+                Greater => node.right.as_deref(),
+                Equal => return Some(node)
+            };
+        }
+        return None;
+    }
+
+    /// build a perfectly balanced tree from the next `len` pairs, which must be in order
+    fn from_sorted<I: Iterator<Item = (K, D)>>(pairs: &mut I, len: usize) -> Link<K, D> {
+        if len == 0 { return None }
+        let left = Self::from_sorted(pairs, len / 2);
+        let (key, data) = pairs.next().expect("fewer pairs than promised");
+        let right = Self::from_sorted(pairs, len - len / 2 - 1);
+        return Some(Box::new(SplayNode { key, data, left, right }));
+    }
+}
+
+impl<K, D> Branch for SplayNode<K, D> {
+    type Key = K;
+    type Data = D;
+
+    fn pair(&self) -> (&K, &D) {
+        return (&self.key, &self.data);
+    }
+
+    fn left(&self) -> Option<&Self> {
+        return self.left.as_deref();
+    }
+
+    fn right(&self) -> Option<&Self> {
+        return self.right.as_deref();
+    }
+
+    fn unlink(self: Box<Self>) -> ((K, D), Link<K, D>, Link<K, D>) {
+        let SplayNode { key, data, left, right } = *self;
+        return ((key, data), left, right);
+    }
+}
+
+/// a map ordered by `C`, which defaults to the keys' `Ord` implementation, that moves every
+/// key it touches to the root. it has the same interface as `AVLTree`, except that `get`
+/// takes `&mut self`: lookups reshape the tree. when a few keys take most of the accesses
+/// they stay within a few links of the root, where `AVLTree` keeps them wherever balance
+/// puts them
+pub struct SplayTree<K, D, C = Natural> {
+    root: Link<K, D>,
+    cmp: C,
+    len: usize,
+}
+
+impl<K, D> SplayTree<K, D>
+where K: Ord + Eq + Clone + fmt::Debug, D: Clone + fmt::Debug
+{
+    pub fn new() -> Self {
+        return Self::with_comparator(Natural);
+    }
+}
+
+impl<K, D, C> SplayTree<K, D, C> {
+    pub fn iter(&self) -> SplayIter<'_, K, D> {
+        return Walk::new(self.root.as_deref(), IterType::DFInOrder);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.len;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len == 0;
+    }
+
+    /// remove every key/data pair
+    pub fn clear(&mut self) {
+        // a splay tree can be one long path, too deep to drop recursively
+        let mut stack: Vec<Box<SplayNode<K, D>>> = self.root.take().into_iter().collect();
+        while let Some(mut node) = stack.pop() {
+            stack.extend(node.left.take());
+            stack.extend(node.right.take());
+        }
+        self.len = 0;
+    }
+
+    /// the comparator that orders this tree's keys
+    pub fn comparator(&self) -> &C {
+        return &self.cmp;
+    }
+
+    /// number of nodes on the longest path from the root down. there is no bound on this
+    /// beyond `len`, only on the average cost of reaching a node
+    pub fn height(&self) -> usize {
+        let mut deepest = 0;
+        let mut stack = Vec::from_iter(self.root.as_deref().map(|root| (root, 1)));
+        while let Some((node, depth)) = stack.pop() {
+            deepest = deepest.max(depth);
+            stack.extend(node.left.as_deref().map(|left| (left, depth + 1)));
+            stack.extend(node.right.as_deref().map(|right| (right, depth + 1)));
+        }
+        return deepest;
+    }
+}
+
+impl<K, D, C> SplayTree<K, D, C>
+where K: Clone + fmt::Debug, D: Clone + fmt::Debug, C: Compare<K>
+{
+    /// create an empty tree ordered by `cmp`
+    pub fn with_comparator(cmp: C) -> Self {
+        return Self { root: None, cmp, len: 0 };
+    }
+
+    /// insert a new key/data pair into the tree, leaving it at the root
+    /// if the key was already present, its data is replaced and the old data returned
+    pub fn put(&mut self, key: K, data: D) -> Option<D> {
+        let Some(root) = self.root.take() else {
+            self.root = Some(SplayNode::new(key, data));
+            self.len = 1;
+            return None;
+        };
+        let mut root = SplayNode::splay(root, &key, &self.cmp);
+        let node = match self.cmp.compare(&key, &root.key) {
+            Equal => {
+                root.key = key;
+                let old = mem::replace(&mut root.data, data);
+                self.root = Some(root);
+                return Some(old);
+            }
+            // the old root is the new key's neighbour, so it and one of its subtrees go
+            // on the far side of the new root
+            Less => {
+                let mut node = SplayNode::new(key, data);
+                node.left = root.left.take();
+                node.right = Some(root);
+                node
+            }
+            Greater => {
+                let mut node = SplayNode::new(key, data);
+                node.right = root.right.take();
+                node.left = Some(root);
+                node
+            }
+        };
+        self.root = Some(node);
+        self.len += 1;
+        return None;
+    }
+
+    /// get a copy of the data associated with a given key, moving it to the root
+    pub fn get(&mut self, key: K) -> Option<D> {
+        return self.splay(&key).map(|node| node.data.clone());
+    }
+
+    /// iterate in order over the key/data pairs whose keys fall within `range`, without
+    /// splaying
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> SplayRange<'_, K, D, C> {
+        return Span::new(self.root.as_deref(), range, &self.cmp);
+    }
+
+    /// delete the node specified by key and return its data
+    pub fn del(&mut self, key: K) -> Result<D, Error> {
+        if self.splay(&key).is_none() { return Err(Error::KeyNotFound) }
+        let mut root = self.root.take().expect("splayed to an empty tree");
+        // every key on the left is smaller, so splaying it for this key brings up its
+        // largest, which has no right child to make room for the right subtree
+        self.root = match root.left.take() {
+            None => root.right.take(),
+            Some(left) => {
+                let mut left = SplayNode::splay(left, &key, &self.cmp);
+                left.right = root.right.take();
+                Some(left)
+            }
+        };
+        self.len -= 1;
+        return Ok(root.data);
+    }
+
+    /// check that the keys are in `cmp` order and that `len` matches the node count. a splay
+    /// tree has no shape invariant to check
+    pub fn validate(&self) -> Result<(), Error> {
+        let mut count = 0;
+        let mut prev: Option<&K> = None;
+        for (key, _) in self.iter() {
+            if prev.is_some_and(|prev| self.cmp.compare(prev, key) != Less) {
+                return Err(Error::InvariantViolation(format!("key {:?} is out of order", key)));
+            }
+            prev = Some(key);
+            count += 1;
+        }
+        if count != self.len {
+            return Err(Error::InvariantViolation(format!("{} nodes but a length of {}", count, self.len)));
+        }
+        return Ok(());
+    }
+
+    /// return a vector of cloned key/value tuples
+    pub fn items(&self) -> Vec<(K,D)> {
+        return self.iter().map(|(key, data)| (key.clone(), data.clone())).collect();
+    }
+
+    /// look a key up without splaying, for callers that only borrow the tree
+    fn find<Q>(&self, key: &Q) -> Option<&SplayNode<K, D>>
+    where K: Borrow<Q>, Q: ?Sized, C: Compare<Q>
+    {
+        return self.root.as_ref()?.get(key, &self.cmp);
+    }
+
+    /// splay `key` to the root, returning the root if it holds the key
+    fn splay(&mut self, key: &K) -> Option<&SplayNode<K, D>> {
+        let root = SplayNode::splay(self.root.take()?, key, &self.cmp);
+        let root = self.root.insert(root);
+        return if self.cmp.compare(key, &root.key) == Equal { Some(root) } else { None };
+    }
+}
+
+impl<K, D, C> Drop for SplayTree<K, D, C> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+/// iterates over a splay tree's key/data pairs in any of the `IterType` orders, in order by
+/// default. iterating doesn't splay
+pub type SplayIter<'a, K, D> = Walk<'a, SplayNode<K, D>>;
+
+/// owning in-order iterator over a splay tree's key/data pairs
+pub type SplayIntoIter<K, D> = Drain<SplayNode<K, D>>;
+
+/// iterates in order over the pairs of a splay tree whose keys fall within a range. ranging
+/// doesn't splay
+pub type SplayRange<'a, K, D, C = Natural> = Span<'a, SplayNode<K, D>, C>;
+
+impl<'a, K, D, C> IntoIterator for &'a SplayTree<K, D, C> {
+    type Item = (&'a K, &'a D);
+    type IntoIter = SplayIter<'a, K, D>;
+
+    fn into_iter(self) -> SplayIter<'a, K, D> {
+        return self.iter();
+    }
+}
+
+impl<K, D, C> IntoIterator for SplayTree<K, D, C> {
+    type Item = (K, D);
+    type IntoIter = SplayIntoIter<K, D>;
+
+    fn into_iter(mut self) -> SplayIntoIter<K, D> {
+        self.len = 0;
+        return Drain::new(self.root.take());
+    }
+}
+
+impl<K, D> From<BTreeMap<K,D>> for SplayTree<K, D>
+where K: Ord + Eq + Clone + fmt::Debug, D: Clone + fmt::Debug
+{
+    /// already in order, so the tree starts out balanced
+    fn from(nodes: BTreeMap<K,D>) -> SplayTree<K, D> {
+        let len = nodes.len();
+        let root = SplayNode::from_sorted(&mut nodes.into_iter(), len);
+        return SplayTree { root, cmp: Natural, len };
+    }
+}
+
+impl<K: Clone, D: Clone, C: Clone> Clone for SplayTree<K, D, C> {
+    /// the copy is balanced, whatever shape this tree has been splayed into
+    fn clone(&self) -> Self {
+        let mut pairs = self.iter().map(|(key, data)| (key.clone(), data.clone()));
+        let root = SplayNode::from_sorted(&mut pairs, self.len);
+        return Self { root, cmp: self.cmp.clone(), len: self.len };
+    }
+}
+
+impl<K, D, C: Default> Default for SplayTree<K, D, C> {
+    fn default() -> Self {
+        return Self { root: None, cmp: C::default(), len: 0 };
+    }
+}
+
+map_traits!(SplayTree);
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use core::cmp::Ordering;
+    use core::hash::{Hash, Hasher};
+    use core::ops::Bound;
+
+    fn test_put_set<K,D> (xs: HashMap<K, D>)
+        where K: Ord + Eq + Clone + fmt::Debug,
+              D: Ord + Eq + Clone + fmt::Debug
+    {
+        let mut vec: Vec<(K,D)> = xs.iter().map(|(x,y)| (x.clone(),y.clone())).collect();
+        let tree = SplayTree::from(&vec);
+        vec.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(tree.items(), vec);
+        assert_eq!(tree.validate(), Ok(()));
+    }
+
+    #[quickcheck]
+    fn qc_test_put_set_isize_isize (xs: HashMap<isize, isize>) {
+        test_put_set(xs);
+    }
+
+    #[quickcheck]
+    fn qc_test_put_set_isize_string (xs: HashMap<isize, String>) {
+        test_put_set(xs);
+    }
+
+    #[quickcheck]
+    fn qc_test_put_set_string_string (xs: HashMap<String, String>) {
+        test_put_set(xs);
+    }
+
+    #[cfg(feature = "std")]
+    #[quickcheck]
+    fn qc_test_put_duplicate(xs: HashMap<isize, isize>, key: isize) {
+        let mut tree = SplayTree::from(&xs);
+        let mut expected = xs.clone();
+        assert_eq!(tree.put(key, 0), expected.insert(key, 0));
+
+        let mut vec: Vec<(isize,isize)> = expected.into_iter().collect();
+        vec.sort();
+        assert_eq!(tree.items(), vec);
+        assert_eq!(tree.validate(), Ok(()));
+    }
+
+    #[cfg(feature = "std")]
+    #[quickcheck]
+    fn qc_test_del_missing(xs: HashMap<isize, isize>, key: isize) {
+        if xs.contains_key(&key) { return }
+        let mut tree = SplayTree::from(&xs);
+        let before = tree.items();
+        assert_eq!(tree.del(key), Err(Error::KeyNotFound));
+        assert_eq!(tree.items(), before);
+        assert_eq!(tree.validate(), Ok(()));
+    }
+
+    /// the tree stays ordered and matches a `BTreeMap` through any mix of operations, and
+    /// every key that is found ends up at the root
+    #[quickcheck]
+    fn qc_test_ops(ops: Vec<(u8, u8, u8)>) {
+        let mut tree = SplayTree::new();
+        let mut model = BTreeMap::new();
+        for (op, key, data) in ops {
+            match op % 3 {
+                0 => assert_eq!(tree.put(key, data), model.insert(key, data)),
+                1 => assert_eq!(tree.get(key), model.get(&key).cloned()),
+                _ => assert_eq!(tree.del(key).ok(), model.remove(&key)),
+            }
+            if op % 3 != 2 && model.contains_key(&key) {
+                assert_eq!(tree.root.as_ref().map(|root| root.key), Some(key));
+            }
+            assert_eq!(tree.validate(), Ok(()));
+        }
+        assert_eq!(BTreeMap::from(tree), model);
+    }
+
+    /// ranging finds the same pairs as a `BTreeMap` and leaves the shape alone
+    #[quickcheck]
+    fn qc_test_range(xs: BTreeMap<u8, u8>, from: u8, to: u8) {
+        let tree = SplayTree::from(xs.clone());
+        let shape = |tree: &SplayTree<u8, u8>| {
+            tree.iter().with_type(IterType::DFPreOrder).map(|(key, _)| *key).collect::<Vec<_>>()
+        };
+        let before = shape(&tree);
+        let (from, to) = (from.min(to), from.max(to));
+        assert!(tree.range(from..to).eq(xs.range(from..to)));
+        assert!(tree.range((Bound::Excluded(from), Bound::Included(to)))
+            .eq(xs.range((Bound::Excluded(from), Bound::Included(to)))));
+        assert!(tree.range(..=from).eq(xs.range(..=from)));
+        assert!(tree.range(to..).eq(xs.range(to..)));
+        assert_eq!(shape(&tree), before);
+    }
+
+    #[test]
+    fn test_hot_keys() {
+        let mut tree = SplayTree::from((0..1000).map(|i| (i, i)).collect::<BTreeMap<_, _>>());
+        assert_eq!(tree.height(), 10);
+        for _ in 0..10 {
+            for hot in [17, 900, 452].iter() {
+                assert_eq!(tree.get(*hot), Some(*hot));
+            }
+        }
+        // the last key used is the root, with the one used before it just below
+        let top: Vec<i32> = tree.iter().with_type(IterType::BF).take(3).map(|(key, _)| *key).collect();
+        assert_eq!(top[0], 452);
+        assert!(top.contains(&900));
+        assert_eq!(tree.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_long_path() {
+        // in-order inserts leave every node the left child of the next, a path of `len`
+        // nodes, which must still be walked, cloned and dropped without recursing down it
+        let mut tree = SplayTree::new();
+        for i in 0..200_000 {
+            tree.put(i, ());
+        }
+        assert_eq!(tree.height(), 200_000);
+        assert_eq!(tree.validate(), Ok(()));
+        assert_eq!(tree.clone().height(), 18);
+
+        // one lookup at the bottom roughly halves the path
+        assert_eq!(tree.get(0), Some(()));
+        assert!(tree.height() <= 100_002);
+        assert_eq!(tree.into_iter().take(3).count(), 3);
+    }
+
+    #[test]
+    fn test_validate() {
+        let tree = |root: Link<u8, ()>, len| SplayTree { root, cmp: Natural, len };
+        let node = |key, left, right| Some(Box::new(SplayNode { key, data: (), left, right }));
+
+        assert_eq!(tree(node(1, node(0, None, None), None), 2).validate(), Ok(()));
+        let unordered = node(1, node(2, None, None), None);
+        assert!(matches!(tree(unordered, 2).validate(), Err(Error::InvariantViolation(_))));
+        let miscounted = node(1, node(0, None, None), None);
+        assert!(matches!(tree(miscounted, 3).validate(), Err(Error::InvariantViolation(_))));
+    }
+
+    #[quickcheck]
+    fn qc_test_reverse_comparator(xs: HashMap<isize, isize>) {
+        let mut tree = SplayTree::with_comparator(|a: &isize, b: &isize| b.cmp(a));
+        for (k, d) in xs.iter() {
+            tree.put(*k, *d);
+        }
+        assert_eq!(tree.validate(), Ok(()));
+
+        let mut vec: Vec<(isize,isize)> = xs.into_iter().collect();
+        vec.sort_by_key(|&(k, _)| std::cmp::Reverse(k));
+        assert_eq!(tree.items(), vec);
+
+        for (k, d) in vec {
+            assert_eq!(tree.get(k), Some(d));
+            assert_eq!(tree.del(k), Ok(d));
+            assert_eq!(tree.validate(), Ok(()));
+        }
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn test_case_insensitive_comparator() {
+        let mut tree = SplayTree::with_comparator(|a: &String, b: &String| a.to_lowercase().cmp(&b.to_lowercase()));
+        tree.put(String::from("banana"), 1);
+        tree.put(String::from("Apple"), 2);
+        tree.put(String::from("cherry"), 3);
+        assert_eq!(tree.put(String::from("APPLE"), 4), Some(2));
+
+        assert_eq!(tree.get(String::from("apple")), Some(4));
+        let keys: Vec<&String> = tree.iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec!["APPLE", "banana", "cherry"]);
+        assert_eq!(tree.del(String::from("Banana")), Ok(1));
+        assert_eq!(tree.get(String::from("banana")), None);
+    }
+
+    #[quickcheck]
+    fn qc_test_eq_ignores_shape(xs: HashMap<isize, isize>, probes: Vec<isize>) {
+        let vec: Vec<(isize,isize)> = xs.into_iter().collect();
+        let forward: SplayTree<isize,isize> = vec.iter().cloned().collect();
+        let mut backward = SplayTree::new();
+        backward.extend(vec.iter().rev().map(|(k, d)| (k, d)));
+        for probe in probes {
+            backward.get(probe);
+        }
+
+        assert_eq!(forward, backward);
+        assert_eq!(forward.cmp(&backward), Ordering::Equal);
+
+        let hash = |tree: &SplayTree<isize,isize>| {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            tree.hash(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash(&forward), hash(&backward));
+
+        let cloned = forward.clone();
+        assert_eq!(cloned, forward);
+        assert_eq!(cloned.validate(), Ok(()));
+    }
+
+    #[quickcheck]
+    fn qc_test_btreemap_roundtrip(xs: BTreeMap<isize, String>) {
+        let tree = SplayTree::from(xs.clone());
+        assert_eq!(tree.validate(), Ok(()));
+        for (k, d) in xs.iter() {
+            assert_eq!(&tree[k], d);
+        }
+        let back: BTreeMap<isize, String> = tree.into();
+        assert_eq!(back, xs);
+    }
+
+    #[test]
+    fn test_std_traits() {
+        let tree = SplayTree::from([(3, "c"), (1, "a"), (2, "b")]);
+        assert_eq!(tree.items(), vec![(1, "a"), (2, "b"), (3, "c")]);
+        assert_eq!(tree[&2], "b");
+        assert_eq!(format!("{:?}", tree), r#"{1: "a", 2: "b", 3: "c"}"#);
+
+        let empty: SplayTree<String, isize> = SplayTree::default();
+        assert!(empty.is_empty());
+
+        // borrowed lookups: String keys indexed by &str
+        let mut names: SplayTree<String, usize> = SplayTree::default();
+        names.extend(vec![(String::from("one"), 1), (String::from("two"), 2)]);
+        assert_eq!(names["two"], 2);
+
+        let owned: Vec<(String, usize)> = names.into_iter().collect();
+        assert_eq!(owned, vec![(String::from("one"), 1), (String::from("two"), 2)]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_index_missing() {
+        let tree = SplayTree::from([(1, 1)]);
+        let _ = tree[&2];
+    }
+
+    #[quickcheck]
+    fn qc_test_len(puts: Vec<(u8, u8)>, dels: Vec<u8>) {
+        let mut tree = SplayTree::new();
+        let mut model = BTreeMap::new();
+        for (k, d) in puts {
+            tree.put(k, d);
+            model.insert(k, d);
+            assert_eq!(tree.len(), model.len());
+        }
+        for k in dels {
+            assert_eq!(tree.del(k).ok(), model.remove(&k));
+            assert_eq!(tree.len(), model.len());
+        }
+        assert_eq!(tree.is_empty(), model.is_empty());
+
+        tree.clear();
+        assert_eq!(tree.len(), 0);
+        assert!(tree.iter().next().is_none());
+    }
+}
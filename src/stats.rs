@@ -0,0 +1,143 @@
+use crate::Rotation;
+use core::sync::atomic::{AtomicU64, Ordering::Relaxed};
+
+/// operation counts for a single tree, collected when the `stats` feature is enabled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Stats {
+    /// key comparisons made by lookups, ranges, inserts and deletes
+    pub comparisons: u64,
+    pub rotate_left: u64,
+    pub rotate_right: u64,
+    pub rotate_left_right: u64,
+    pub rotate_right_left: u64,
+    /// nodes allocated by the tree itself. nodes handed to `ins` are not counted
+    pub allocations: u64,
+    /// nodes checked for balance on the way back up from an insert or delete
+    pub rebalances: u64,
+}
+
+impl Stats {
+    pub fn single_rotations(&self) -> u64 {
+        return self.rotate_left + self.rotate_right;
+    }
+
+    pub fn double_rotations(&self) -> u64 {
+        return self.rotate_left_right + self.rotate_right_left;
+    }
+}
+
+/* the live counters. they're atomic rather than `Cell`s so that lookups can count through
+ * `&self` without making the tree `!Sync`
+ */
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    comparisons: AtomicU64,
+    rotate_left: AtomicU64,
+    rotate_right: AtomicU64,
+    rotate_left_right: AtomicU64,
+    rotate_right_left: AtomicU64,
+    allocations: AtomicU64,
+    rebalances: AtomicU64,
+}
+
+impl Counters {
+    pub(crate) fn compared(&self) {
+        self.comparisons.fetch_add(1, Relaxed);
+    }
+
+    pub(crate) fn rotated(&self, rotation: Rotation) {
+        let counter = match rotation {
+            Rotation::Left => &self.rotate_left,
+            Rotation::Right => &self.rotate_right,
+            Rotation::LeftRight => &self.rotate_left_right,
+            Rotation::RightLeft => &self.rotate_right_left,
+        };
+        counter.fetch_add(1, Relaxed);
+    }
+
+    pub(crate) fn allocated(&self, count: usize) {
+        self.allocations.fetch_add(count as u64, Relaxed);
+    }
+
+    pub(crate) fn rebalanced(&self) {
+        self.rebalances.fetch_add(1, Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> Stats {
+        return Stats {
+            comparisons: self.comparisons.load(Relaxed),
+            rotate_left: self.rotate_left.load(Relaxed),
+            rotate_right: self.rotate_right.load(Relaxed),
+            rotate_left_right: self.rotate_left_right.load(Relaxed),
+            rotate_right_left: self.rotate_right_left.load(Relaxed),
+            allocations: self.allocations.load(Relaxed),
+            rebalances: self.rebalances.load(Relaxed),
+        };
+    }
+
+    pub(crate) fn reset(&self) {
+        for counter in [&self.comparisons, &self.rotate_left, &self.rotate_right, &self.rotate_left_right,
+                        &self.rotate_right_left, &self.allocations, &self.rebalances].iter() {
+            counter.store(0, Relaxed);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AVLTree;
+
+    #[test]
+    fn test_rotations() {
+        let mut tree = AVLTree::new();
+        for key in [1, 2, 3].iter() { tree.put(*key, ()); }
+        assert_eq!(tree.stats().rotate_left, 1);
+
+        let mut tree = AVLTree::new();
+        for key in [3, 1, 2].iter() { tree.put(*key, ()); }
+        let stats = tree.stats();
+        assert_eq!(stats.rotate_left_right, 1);
+        assert_eq!(stats.single_rotations(), 0);
+        assert_eq!(stats.double_rotations(), 1);
+        assert_eq!(stats.allocations, 3);
+    }
+
+    #[test]
+    fn test_reset() {
+        let tree = AVLTree::from_sorted_iter((0..100).map(|key| (key, key)));
+        assert_eq!(tree.stats().allocations, 100);
+        tree.reset_stats();
+        assert_eq!(tree.stats(), Stats::default());
+
+        // a single node, so one comparison and nothing to rebalance
+        let mut tree = AVLTree::new();
+        tree.put(1, 1);
+        tree.reset_stats();
+        assert_eq!(tree.get(1), Some(1));
+        assert_eq!(tree.stats(), Stats { comparisons: 1, ..Stats::default() });
+
+        // ranges count too: one comparison against the lower bound on the way down, and one
+        // against the upper bound for each key checked on the way out
+        tree.reset_stats();
+        assert_eq!(tree.range(0..=1).count(), 1);
+        assert_eq!(tree.stats(), Stats { comparisons: 2, ..Stats::default() });
+    }
+
+    #[quickcheck]
+    fn qc_test_logarithmic(keys: Vec<u16>) {
+        let mut tree = AVLTree::new();
+        for key in keys.iter() {
+            tree.reset_stats();
+            tree.put(*key, ());
+            // an AVL tree of n nodes is at most 1.44 log2(n + 2) high, and every comparison
+            // and rebalance happens at a different level
+            let bound = (1.45 * ((tree.len() + 2) as f64).log2()).ceil() as u64;
+            let stats = tree.stats();
+            assert!(stats.comparisons <= bound, "{:?} over {}", stats, bound);
+            assert!(stats.rebalances <= bound, "{:?} over {}", stats, bound);
+            assert!(stats.single_rotations() + stats.double_rotations() <= 1);
+        }
+    }
+}
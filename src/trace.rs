@@ -0,0 +1,194 @@
+use crate::{AVLTree, Rotation};
+use allocator_api2::alloc::Allocator;
+use core::fmt::{self, Write};
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// one structural change made inside the node algorithms, as recorded by `start_trace`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step<K> {
+    /// a node for a new key was linked in, before the path above it was rebalanced
+    Insert { key: K },
+    /// the key was already present and its node took new data
+    Replace { key: K },
+    /// the node holding the key is about to be unlinked
+    Remove { key: K },
+    /// the node holding `key` was rotated down while rebalancing and the node holding
+    /// `promoted` took its place. balance factors are right height minus left height, of
+    /// `key`'s node before the rotation and of `promoted`'s after it
+    Rotate { rotation: Rotation, key: K, promoted: K, balance_before: isize, balance_after: isize },
+}
+
+/// the steps recorded between `start_trace` and `stop_trace`, in the order they happened
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trace<K> {
+    steps: Vec<Step<K>>,
+}
+
+impl<K> Trace<K> {
+    pub fn steps(&self) -> &[Step<K>] {
+        return &self.steps;
+    }
+
+    pub fn len(&self) -> usize {
+        return self.steps.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.steps.is_empty();
+    }
+}
+
+impl<K: fmt::Display> Trace<K> {
+    /// the trace as a JSON array with one object, or frame, per step, such as
+    /// `{"frame":3,"step":"rotate","rotation":"LeftRight","key":"8","promoted":"6",
+    /// "balance_before":-2,"balance_after":0}`. keys are written as strings of their
+    /// `Display` form, whatever their type
+    pub fn to_json(&self) -> String {
+        let mut out = String::from("[");
+        for (frame, step) in self.steps.iter().enumerate() {
+            if frame > 0 { out.push(',') }
+            // writing to a `String` can't fail
+            let _ = write!(out, "{{\"frame\":{},\"step\":", frame);
+            let (name, key) = match step {
+                Step::Insert { key } => ("insert", key),
+                Step::Replace { key } => ("replace", key),
+                Step::Remove { key } => ("remove", key),
+                Step::Rotate { key, .. } => ("rotate", key),
+            };
+            let _ = write!(out, "\"{}\"", name);
+            if let Step::Rotate { rotation, .. } = step {
+                let _ = write!(out, ",\"rotation\":\"{:?}\"", rotation);
+            }
+            out.push_str(",\"key\":");
+            push_json_string(&mut out, key);
+            if let Step::Rotate { promoted, balance_before, balance_after, .. } = step {
+                out.push_str(",\"promoted\":");
+                push_json_string(&mut out, promoted);
+                let _ = write!(out, ",\"balance_before\":{},\"balance_after\":{}", balance_before, balance_after);
+            }
+            out.push('}');
+        }
+        out.push(']');
+        return out;
+    }
+}
+
+/// append `value`'s `Display` form to `out` as a quoted, escaped JSON string
+fn push_json_string<T: fmt::Display>(out: &mut String, value: &T) {
+    let mut text = String::new();
+    let _ = write!(text, "{}", value);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => { let _ = write!(out, "\\u{:04x}", c as u32); }
+            c => out.push(c)
+        }
+    }
+    out.push('"');
+}
+
+impl<K, D, C, A: Allocator> AVLTree<K,D,C,A> {
+    /// start recording every insert, replacement, removal and rotation made by the tree's
+    /// own methods, discarding anything recorded so far. `clear`, `retain` and `extract_if`
+    /// record a removal for each key they take out, and rebuild what is left without
+    /// rotating. nothing is recorded until this is called, and trees built in one pass, such
+    /// as by `from_sorted_iter`, make no steps
+    pub fn start_trace(&mut self) {
+        self.trace = Some(Vec::new());
+    }
+
+    /// stop recording and return what was recorded, which is empty if recording was never
+    /// started
+    pub fn stop_trace(&mut self) -> Trace<K> {
+        return Trace { steps: self.trace.take().unwrap_or_default() };
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trace() {
+        let mut tree = AVLTree::new();
+        tree.put(1, ());
+        tree.start_trace();
+        tree.put(3, ());
+        tree.put(2, ());
+        tree.put(2, ());
+        tree.del(1).unwrap();
+        let trace = tree.stop_trace();
+        assert_eq!(trace.steps(), &[
+            Step::Insert { key: 3 },
+            Step::Insert { key: 2 },
+            // 2 went in left of 3, right of 1, so 1 is right-heavy with a left-heavy child
+            Step::Rotate { rotation: Rotation::RightLeft, key: 1, promoted: 2, balance_before: 2, balance_after: 0 },
+            Step::Replace { key: 2 },
+            Step::Remove { key: 1 },
+        ]);
+
+        // nothing is recorded once stopped
+        tree.put(4, ());
+        assert!(tree.stop_trace().is_empty());
+    }
+
+    #[test]
+    fn test_to_json() {
+        let mut tree = AVLTree::new();
+        tree.start_trace();
+        for key in ["a", "b", "c\"d"].iter() {
+            tree.put(String::from(*key), ());
+        }
+        assert_eq!(tree.stop_trace().to_json(), concat!(
+            r#"[{"frame":0,"step":"insert","key":"a"},"#,
+            r#"{"frame":1,"step":"insert","key":"b"},"#,
+            r#"{"frame":2,"step":"insert","key":"c\"d"},"#,
+            r#"{"frame":3,"step":"rotate","rotation":"Left","key":"a","promoted":"b","balance_before":2,"balance_after":0}]"#));
+        assert_eq!(Trace::<u8> { steps: Vec::new() }.to_json(), "[]");
+    }
+
+    #[test]
+    fn test_trace_bulk_removals() {
+        let mut tree: AVLTree<u8, ()> = (0..6).map(|key| (key, ())).collect();
+        tree.start_trace();
+        tree.retain(|key, _| key % 2 == 0);
+        let extracted: Vec<u8> = tree.extract_if(|key, _| *key == 4).map(|(key, _)| key).collect();
+        tree.clear();
+        assert_eq!(extracted, vec![4]);
+        let removed: Vec<u8> = tree.stop_trace().steps().iter().map(|step| match step {
+            Step::Remove { key } => *key,
+            step => panic!("unexpected step {:?}", step),
+        }).collect();
+        assert_eq!(removed, vec![1, 3, 5, 4, 0, 2]);
+    }
+
+    /// every rotation leaves its subtree balanced, and the trace accounts for every key
+    #[quickcheck]
+    fn qc_test_rotations(ops: Vec<(bool, u8)>) {
+        let mut tree = AVLTree::new();
+        tree.start_trace();
+        for (put, key) in ops {
+            if put { tree.put(key, ()); } else { let _ = tree.del(key); }
+        }
+        let mut live = std::collections::BTreeSet::new();
+        for step in tree.stop_trace().steps() {
+            match step {
+                Step::Insert { key } => assert!(live.insert(*key)),
+                Step::Replace { key } => assert!(live.contains(key)),
+                Step::Remove { key } => assert!(live.remove(key)),
+                Step::Rotate { balance_before, balance_after, .. } => {
+                    assert_eq!(balance_before.abs(), 2);
+                    assert!(balance_after.abs() <= 1);
+                }
+            }
+        }
+        assert!(live.into_iter().eq(tree.iter().map(|(key, _)| *key)));
+    }
+}
@@ -0,0 +1,149 @@
+use crate::{AVLTree, Compare, Error};
+use crate::node::Quiet;
+use allocator_api2::alloc::Allocator;
+use core::fmt;
+use alloc::vec::Vec;
+
+/// a batch of changes to an `AVLTree`, staged apart from the tree until the transaction
+/// commits. lookups see the staged changes on top of the tree
+pub struct Transaction<'a, K, D, C, A: Allocator> {
+    tree: &'a AVLTree<K,D,C,A>,
+    // `None` marks a key deleted by the transaction
+    staged: AVLTree<K, Option<D>, Quiet<'a, C>>,
+}
+
+impl<'a, K, D, C, A> Transaction<'a, K, D, C, A>
+where K: Clone + fmt::Debug, D: Clone + fmt::Debug, C: Compare<K>, A: Allocator + Clone
+{
+    fn new(tree: &'a AVLTree<K,D,C,A>) -> Self {
+        return Self { tree, staged: AVLTree::with_comparator(Quiet(tree.comparator())) };
+    }
+
+    /// get a copy of the data associated with a given key, as of the changes made so far
+    pub fn get(&self, key: K) -> Option<D> {
+        return match self.staged.find(&key) {
+            Some(node) => node.data.clone(),
+            None => self.tree.find(&key).map(|node| node.data.clone())
+        };
+    }
+
+    pub fn contains_key(&self, key: K) -> bool {
+        return self.get(key).is_some();
+    }
+
+    /// stage a key/data pair. returns the data it replaces, if any
+    pub fn put(&mut self, key: K, data: D) -> Option<D> {
+        let old = self.get(key.clone());
+        self.staged.put(key, Some(data));
+        return old;
+    }
+
+    /// stage the deletion of a key and return its data
+    pub fn del(&mut self, key: K) -> Result<D, Error> {
+        let old = self.get(key.clone()).ok_or(Error::KeyNotFound)?;
+        self.staged.put(key, None);
+        return Ok(old);
+    }
+}
+
+impl<K, D, C, A> AVLTree<K,D,C,A>
+where K: Clone + fmt::Debug, D: Clone + fmt::Debug, C: Compare<K>, A: Allocator + Clone
+{
+    /// run `f` against a transaction and apply everything it staged if it returns `Ok`. if
+    /// it returns `Err` or panics the tree is left exactly as it was. observers and stats
+    /// only see the changes as they are applied, in key order
+    pub fn transaction<F, T, E>(&mut self, f: F) -> Result<T, E>
+    where F: FnOnce(&mut Transaction<'_, K, D, C, A>) -> Result<T, E>
+    {
+        let (value, writes) = {
+            let mut tx = Transaction::new(self);
+            let value = f(&mut tx)?;
+            let writes: Vec<(K, Option<D>)> = tx.staged.into_iter().collect();
+            (value, writes)
+        };
+        for (key, data) in writes {
+            match data {
+                Some(data) => { self.put(key, data); }
+                // a key both added and deleted by the transaction is not in the tree
+                None => { let _ = self.del(key); }
+            }
+        }
+        return Ok(value);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    #[test]
+    fn test_commit() {
+        let mut tree = AVLTree::from_sorted_iter((0..10).map(|key| (key, key)));
+        let sum = tree.transaction(|tx| {
+            assert_eq!(tx.put(3, 30), Some(3));
+            assert_eq!(tx.get(3), Some(30));
+            assert_eq!(tx.del(4), Ok(4));
+            assert_eq!(tx.get(4), None);
+            assert_eq!(tx.del(4), Err(Error::KeyNotFound));
+            assert_eq!(tx.put(20, 200), None);
+            assert_eq!(tx.del(20), Ok(200));
+            Ok::<_, Error>(tx.get(3).unwrap() + tx.get(5).unwrap())
+        });
+        assert_eq!(sum, Ok(35));
+        assert_eq!(tree.get(3), Some(30));
+        assert_eq!(tree.get(4), None);
+        assert_eq!(tree.get(20), None);
+        assert_eq!(tree.len(), 9);
+        assert_eq!(tree.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_rollback() {
+        let mut tree = AVLTree::from_sorted_iter((0..10).map(|key| (key, key)));
+        let before = tree.clone();
+
+        let result = tree.transaction(|tx| {
+            tx.put(3, 30);
+            tx.del(11)?;
+            Ok(())
+        });
+        assert_eq!(result, Err(Error::KeyNotFound));
+        assert_eq!(tree, before);
+
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            tree.transaction(|tx| {
+                tx.del(3)?;
+                panic!("failed halfway");
+                #[allow(unreachable_code)]
+                Ok::<_, Error>(())
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(tree, before);
+    }
+
+    #[quickcheck]
+    fn qc_test_transaction(xs: BTreeMap<u8, u8>, ops: Vec<(bool, u8, u8)>, commit: bool) {
+        let mut tree = AVLTree::from_sorted_iter(xs.clone());
+        let mut model = xs.clone();
+        let result = tree.transaction(|tx| {
+            for (put, key, data) in ops.iter().cloned() {
+                if put {
+                    assert_eq!(tx.put(key, data), model.insert(key, data));
+                } else {
+                    assert_eq!(tx.del(key).ok(), model.remove(&key));
+                }
+                assert_eq!(tx.get(key), model.get(&key).cloned());
+            }
+            if commit { Ok(()) } else { Err(()) }
+        });
+        let expected = if commit { model } else { xs };
+        assert_eq!(result.is_ok(), commit);
+        assert_eq!(BTreeMap::from(tree.clone()), expected);
+        assert_eq!(tree.len(), expected.len());
+        assert_eq!(tree.validate(), Ok(()));
+    }
+}
@@ -0,0 +1,521 @@
+use crate::{Compare, Error, IterType, Natural};
+use crate::walk::{map_traits, Branch, Drain, Span, Walk};
+use core::borrow::Borrow;
+use core::cmp::Ordering::{Equal, Greater, Less};
+use core::fmt;
+use core::ops::RangeBounds;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::vec::Vec;
+
+/* a treap is a binary search tree on its keys and a max-heap on priorities drawn at random
+ * when each key goes in. its shape is the one the keys would have had inserted in priority
+ * order, so with random priorities the expected depth is O(log n) whatever the order the keys
+ * really came in. everything here is built on two operations: `split`, which cuts a treap in
+ * two around a key, and `merge`, which joins two treaps whose keys don't interleave
+ */
+
+type Link<K, D> = Option<Box<TreapNode<K, D>>>;
+
+#[derive(Clone)]
+pub struct TreapNode<K, D> {
+    key: K,
+    data: D,
+    priority: u64,
+    // nodes in the subtree rooted here, so `split` knows how many it moved
+    size: usize,
+    left: Link<K, D>,
+    right: Link<K, D>,
+}
+
+/// the pieces of a treap cut around a key: the keys below it, the node holding it if there is
+/// one, and the keys above it
+type Split<K, D> = (Link<K, D>, Link<K, D>, Link<K, D>);
+
+fn size<K, D>(link: &Link<K, D>) -> usize {
+    return link.as_ref().map_or(0, |node| node.size);
+}
+
+impl<K, D> TreapNode<K, D> {
+    fn new(key: K, data: D, priority: u64) -> Box<Self> {
+        return Box::new(TreapNode { key, data, priority, size: 1, left: None, right: None });
+    }
+
+    fn resize(&mut self) {
+        self.size = size(&self.left) + size(&self.right) + 1;
+    }
+
+    /// cut a treap into the keys less than `key`, the node equal to it and the keys greater
+    fn split<Q, C>(link: Link<K, D>, key: &Q, cmp: &C) -> Split<K, D>
+    where K: Borrow<Q>, Q: ?Sized, C: Compare<Q>
+    {
+        let Some(mut node) = link else { return (None, None, None) };
+        match cmp.compare(key, node.key.borrow()) {
+            Less => {
+                let (less, equal, greater) = Self::split(node.left.take(), key, cmp);
+                node.left = greater;
+                node.resize();
+                return (less, equal, Some(node));
+            }
+            Greater => {
+                let (less, equal, greater) = Self::split(node.right.take(), key, cmp);
+                node.right = less;
+                node.resize();
+                return (Some(node), equal, greater);
+            }
+            Equal => {
+                let (less, greater) = (node.left.take(), node.right.take());
+                node.size = 1;
+                return (less, Some(node), greater);
+            }
+        }
+    }
+
+    /// join two treaps where every key in `left` is less than every key in `right`
+    fn merge(left: Link<K, D>, right: Link<K, D>) -> Link<K, D> {
+        match (left, right) {
+            (None, right) => return right,
+            (left, None) => return left,
+            (Some(mut left), Some(mut right)) => {
+                // the higher priority stays on top, and the other treap merges into its
+                // inner subtree
+                if left.priority >= right.priority {
+                    left.right = Self::merge(left.right.take(), Some(right));
+                    left.resize();
+                    return Some(left);
+                } else {
+                    right.left = Self::merge(Some(left), right.left.take());
+                    right.resize();
+                    return Some(right);
+                }
+            }
+        }
+    }
+
+    fn get<Q, C>(&self, key: &Q, cmp: &C) -> Option<&Self>
+    where K: Borrow<Q>, Q: ?Sized, C: Compare<Q>
+    {
+        let mut curr = Some(self);
+        while let Some(node) = curr {
+            curr = match cmp.compare(key, node.key.borrow()) {
+                Less => node.left.as_deref(),
+                Greater => node.right.as_deref(),
+                Equal => return Some(node)
+            };
+        }
+        return None;
+    }
+
+    fn first(&self) -> &K {
+        let mut node = self;
+        while let Some(left) = node.left.as_deref() { node = left }
+        return &node.key;
+    }
+
+    fn last(&self) -> &K {
+        let mut node = self;
+        while let Some(right) = node.right.as_deref() { node = right }
+        return &node.key;
+    }
+
+    /// check that every key below this node is strictly between `lower` and `upper`, that
+    /// no child outranks its parent and that the cached sizes are right, returning the size
+    fn validate<C: Compare<K>>(&self, cmp: &C, lower: Option<&K>, upper: Option<&K>) -> Result<usize, Error>
+    where K: fmt::Debug
+    {
+        if lower.is_some_and(|lower| cmp.compare(lower, &self.key) != Less)
+            || upper.is_some_and(|upper| cmp.compare(&self.key, upper) != Less) {
+            return Err(Error::InvariantViolation(format!("key {:?} is out of order", self.key)));
+        }
+        for child in [self.left.as_deref(), self.right.as_deref()].iter().flatten() {
+            if child.priority > self.priority {
+                return Err(Error::InvariantViolation(
+                    format!("{:?} has a higher priority than its parent {:?}", child.key, self.key)));
+            }
+        }
+        let left = self.left.as_ref().map_or(Ok(0), |left| left.validate(cmp, lower, Some(&self.key)))?;
+        let right = self.right.as_ref().map_or(Ok(0), |right| right.validate(cmp, Some(&self.key), upper))?;
+        if self.size != left + right + 1 {
+            return Err(Error::InvariantViolation(
+                format!("{:?} has {} nodes below it but a size of {}", self.key, left + right + 1, self.size)));
+        }
+        return Ok(self.size);
+    }
+
+    fn height(&self) -> usize {
+        let left = self.left.as_ref().map_or(0, |left| left.height());
+        let right = self.right.as_ref().map_or(0, |right| right.height());
+        return left.max(right) + 1;
+    }
+}
+
+impl<K, D> Branch for TreapNode<K, D> {
+    type Key = K;
+    type Data = D;
+
+    fn pair(&self) -> (&K, &D) {
+        return (&self.key, &self.data);
+    }
+
+    fn left(&self) -> Option<&Self> {
+        return self.left.as_deref();
+    }
+
+    fn right(&self) -> Option<&Self> {
+        return self.right.as_deref();
+    }
+
+    fn unlink(self: Box<Self>) -> ((K, D), Link<K, D>, Link<K, D>) {
+        let TreapNode { key, data, left, right, .. } = *self;
+        return ((key, data), left, right);
+    }
+}
+
+/// the seed used by `new`, `with_comparator` and `default`
+const DEFAULT_SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+/// splitmix64: a tiny generator whose whole state is one counter, so a treap can carry its
+/// own and be reproduced from a seed
+#[derive(Debug, Clone)]
+struct Priorities {
+    state: u64,
+}
+
+impl Priorities {
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        return z ^ (z >> 31);
+    }
+}
+
+/// a map ordered by `C`, which defaults to the keys' `Ord` implementation, balanced by random
+/// priorities rather than by rotations on every write. it has the same interface as
+/// `AVLTree`, plus `split` and `merge`, which cut and join whole treaps in expected
+/// O(log n). priorities come from a generator that starts at a fixed seed unless one is
+/// given, so a treap built by the same operations always has the same shape
+pub struct Treap<K, D, C = Natural> {
+    root: Link<K, D>,
+    cmp: C,
+    len: usize,
+    priorities: Priorities,
+}
+
+impl<K, D> Treap<K, D>
+where K: Ord + Eq + Clone + fmt::Debug, D: Clone + fmt::Debug
+{
+    pub fn new() -> Self {
+        return Self::with_comparator(Natural);
+    }
+
+    /// create an empty treap whose priorities are generated from `seed`
+    pub fn with_seed(seed: u64) -> Self {
+        return Self::with_comparator_and_seed(Natural, seed);
+    }
+}
+
+impl<K, D, C> Treap<K, D, C> {
+    pub fn iter(&self) -> TreapIter<'_, K, D> {
+        return Walk::new(self.root.as_deref(), IterType::DFInOrder);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.len;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len == 0;
+    }
+
+    /// remove every key/data pair. the priority generator carries on where it was
+    pub fn clear(&mut self) {
+        self.root = None;
+        self.len = 0;
+    }
+
+    /// the comparator that orders this treap's keys
+    pub fn comparator(&self) -> &C {
+        return &self.cmp;
+    }
+
+    /// number of nodes on the longest path from the root down, O(log n) in expectation
+    pub fn height(&self) -> usize {
+        return self.root.as_ref().map_or(0, |root| root.height());
+    }
+}
+
+impl<K, D, C> Treap<K, D, C>
+where K: Clone + fmt::Debug, D: Clone + fmt::Debug, C: Compare<K>
+{
+    /// create an empty treap ordered by `cmp`
+    pub fn with_comparator(cmp: C) -> Self {
+        return Self::with_comparator_and_seed(cmp, DEFAULT_SEED);
+    }
+
+    /// create an empty treap ordered by `cmp`, whose priorities are generated from `seed`
+    pub fn with_comparator_and_seed(cmp: C, seed: u64) -> Self {
+        return Self { root: None, cmp, len: 0, priorities: Priorities { state: seed } };
+    }
+
+    /// insert a new key/data pair into the treap
+    /// if the key was already present, its data is replaced and the old data returned
+    pub fn put(&mut self, key: K, data: D) -> Option<D> {
+        let (less, equal, greater) = TreapNode::split(self.root.take(), &key, &self.cmp);
+        let (node, old) = match equal {
+            // keep the old node, and with it the priority that placed it
+            Some(mut node) => {
+                node.key = key;
+                let old = core::mem::replace(&mut node.data, data);
+                (node, Some(old))
+            }
+            None => {
+                let priority = self.priorities.next();
+                (TreapNode::new(key, data, priority), None)
+            }
+        };
+        self.root = TreapNode::merge(TreapNode::merge(less, Some(node)), greater);
+        if old.is_none() { self.len += 1 }
+        return old;
+    }
+
+    /// get a copy of the data associated with a given key
+    pub fn get(&self, key: K) -> Option<D> {
+        return self.find(&key).map(|node| node.data.clone());
+    }
+
+    /// delete the node specified by key and return its data
+    pub fn del(&mut self, key: K) -> Result<D, Error> {
+        let (less, equal, greater) = TreapNode::split(self.root.take(), &key, &self.cmp);
+        self.root = TreapNode::merge(less, greater);
+        let node = equal.ok_or(Error::KeyNotFound)?;
+        self.len -= 1;
+        return Ok(node.data);
+    }
+
+    /// iterate over the key/data pairs whose keys fall in `range`, in order
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> TreapRange<'_, K, D, C> {
+        return Span::new(self.root.as_deref(), range, &self.cmp);
+    }
+
+    /// move every pair whose key is `key` or greater into a new treap, which is returned.
+    /// the new treap's priorities are seeded from this one's generator
+    pub fn split(&mut self, key: &K) -> Self where C: Clone {
+        let (less, equal, greater) = TreapNode::split(self.root.take(), key, &self.cmp);
+        self.root = less;
+        let upper = TreapNode::merge(equal, greater);
+        let moved = size(&upper);
+        self.len -= moved;
+        let seed = self.priorities.next();
+        return Self { root: upper, cmp: self.cmp.clone(), len: moved, priorities: Priorities { state: seed } };
+    }
+
+    /// move every pair out of `other` and into this treap. when every key of one treap is
+    /// less than every key of the other the two are merged whole; otherwise `other`'s pairs
+    /// are put one at a time, and its data wins for keys found in both
+    pub fn merge(&mut self, mut other: Self) {
+        let (Some(mine), Some(theirs)) = (self.root.as_deref(), other.root.as_deref()) else {
+            if self.root.is_none() {
+                self.root = other.root.take();
+                self.len = other.len;
+            }
+            return;
+        };
+        if self.cmp.compare(mine.last(), theirs.first()) == Less {
+            self.root = TreapNode::merge(self.root.take(), other.root.take());
+        } else if self.cmp.compare(theirs.last(), mine.first()) == Less {
+            self.root = TreapNode::merge(other.root.take(), self.root.take());
+        } else {
+            self.extend(other);
+            return;
+        }
+        self.len += other.len;
+    }
+
+    /// check the treap invariants: keys are in `cmp` order, no node has a higher priority
+    /// than its parent, and `len` and every cached subtree size match the number of nodes
+    pub fn validate(&self) -> Result<(), Error> {
+        let Some(root) = self.root.as_ref() else { return Ok(()) };
+        let count = root.validate(&self.cmp, None, None)?;
+        if count != self.len {
+            return Err(Error::InvariantViolation(format!("{} nodes but a length of {}", count, self.len)));
+        }
+        return Ok(());
+    }
+
+    /// return a vector of cloned key/value tuples
+    pub fn items(&self) -> Vec<(K,D)> {
+        return self.iter().map(|(key, data)| (key.clone(), data.clone())).collect();
+    }
+
+    fn find<Q>(&self, key: &Q) -> Option<&TreapNode<K, D>>
+    where K: Borrow<Q>, Q: ?Sized, C: Compare<Q>
+    {
+        return self.root.as_ref()?.get(key, &self.cmp);
+    }
+}
+
+/// iterates over a treap's key/data pairs in any of the `IterType` orders, in order by default
+pub type TreapIter<'a, K, D> = Walk<'a, TreapNode<K, D>>;
+
+/// owning in-order iterator over a treap's key/data pairs
+pub type TreapIntoIter<K, D> = Drain<TreapNode<K, D>>;
+
+/// iterates in order over the pairs of a treap whose keys fall within a range
+pub type TreapRange<'a, K, D, C = Natural> = Span<'a, TreapNode<K, D>, C>;
+
+impl<'a, K, D, C> IntoIterator for &'a Treap<K, D, C> {
+    type Item = (&'a K, &'a D);
+    type IntoIter = TreapIter<'a, K, D>;
+
+    fn into_iter(self) -> TreapIter<'a, K, D> {
+        return self.iter();
+    }
+}
+
+impl<K, D, C> IntoIterator for Treap<K, D, C> {
+    type Item = (K, D);
+    type IntoIter = TreapIntoIter<K, D>;
+
+    fn into_iter(self) -> TreapIntoIter<K, D> {
+        return Drain::new(self.root);
+    }
+}
+
+impl<K, D> From<BTreeMap<K,D>> for Treap<K, D>
+where K: Ord + Eq + Clone + fmt::Debug, D: Clone + fmt::Debug
+{
+    fn from(nodes: BTreeMap<K,D>) -> Treap<K, D> {
+        return nodes.into_iter().collect();
+    }
+}
+
+impl<K: Clone, D: Clone, C: Clone> Clone for Treap<K, D, C> {
+    /// the copy has the same shape, and its generator picks up where this one's is
+    fn clone(&self) -> Self {
+        return Self {
+            root: self.root.clone(),
+            cmp: self.cmp.clone(),
+            len: self.len,
+            priorities: self.priorities.clone(),
+        };
+    }
+}
+
+impl<K, D, C: Default> Default for Treap<K, D, C> {
+    fn default() -> Self {
+        return Self { root: None, cmp: C::default(), len: 0, priorities: Priorities { state: DEFAULT_SEED } };
+    }
+}
+
+map_traits!(Treap);
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::iter::FromIterator;
+    use core::ops::Bound;
+
+    /// the treap stays valid and matches a `BTreeMap` through any mix of puts, deletes and
+    /// range queries
+    #[quickcheck]
+    fn qc_test_ops(ops: Vec<(u8, u8, u8)>, seed: u64) {
+        let mut treap = Treap::with_seed(seed);
+        let mut model = BTreeMap::new();
+        for (op, key, data) in ops {
+            match op % 3 {
+                0 => assert_eq!(treap.put(key, data), model.insert(key, data)),
+                1 => assert_eq!(treap.del(key).ok(), model.remove(&key)),
+                _ => {
+                    let (from, to) = (key.min(data), key.max(data));
+                    assert!(treap.range(from..to).eq(model.range(from..to)));
+                    assert!(treap.range((Bound::Excluded(from), Bound::Included(to)))
+                        .eq(model.range((Bound::Excluded(from), Bound::Included(to)))));
+                    assert!(treap.range(..=from).eq(model.range(..=from)));
+                }
+            }
+            assert_eq!(treap.validate(), Ok(()));
+        }
+        assert_eq!(BTreeMap::from(treap), model);
+    }
+
+    #[quickcheck]
+    fn qc_test_split_merge(xs: BTreeMap<u16, u16>, at: u16) {
+        let mut lower = Treap::from(xs.clone());
+        let upper = lower.split(&at);
+        assert_eq!(lower.validate(), Ok(()));
+        assert_eq!(upper.validate(), Ok(()));
+        assert!(lower.iter().eq(xs.range(..at)));
+        assert!(upper.iter().eq(xs.range(at..)));
+
+        // disjoint halves merge whole, whichever side they come from
+        let mut rejoined = upper.clone();
+        rejoined.merge(lower.clone());
+        assert_eq!(rejoined.validate(), Ok(()));
+        assert!(rejoined.iter().eq(xs.iter()));
+        lower.merge(upper);
+        assert_eq!(lower.validate(), Ok(()));
+        assert_eq!(lower.items(), xs.into_iter().collect::<Vec<_>>());
+    }
+
+    #[quickcheck]
+    fn qc_test_merge_overlapping(xs: BTreeMap<u8, u8>, ys: BTreeMap<u8, u8>) {
+        let mut treap = Treap::from(xs.clone());
+        treap.merge(Treap::from(ys.clone()));
+        let mut expected = xs;
+        expected.extend(ys);
+        assert_eq!(treap.validate(), Ok(()));
+        assert_eq!(BTreeMap::from(treap), expected);
+    }
+
+    #[test]
+    fn test_seeds() {
+        let shape = |treap: &Treap<u32, ()>| {
+            treap.iter().with_type(IterType::DFPreOrder).map(|(key, _)| *key).collect::<Vec<_>>()
+        };
+        let build = |seed| {
+            let mut treap = Treap::with_seed(seed);
+            treap.extend((0..1000).map(|i| (i, ())));
+            treap
+        };
+        // the same seed always grows the same shape, and a different one almost surely doesn't
+        assert_eq!(shape(&build(7)), shape(&build(7)));
+        assert_ne!(shape(&build(7)), shape(&build(8)));
+        assert_eq!(shape(&Treap::from_iter((0..1000).map(|i| (i, ())))), shape(&build(DEFAULT_SEED)));
+
+        // keys in order would make a path of an unbalanced tree, but priorities don't care
+        let treap = build(7);
+        assert_eq!(treap.validate(), Ok(()));
+        assert!(treap.height() < 40);
+    }
+
+    #[test]
+    fn test_validate() {
+        let valid = Treap::from([(0u8, ()), (1, ())]);
+        assert_eq!(valid.validate(), Ok(()));
+
+        // break one invariant at a time on copies of a two-node treap
+        let mut unordered = valid.clone();
+        let root = unordered.root.as_deref_mut().unwrap();
+        let child = root.left.as_deref_mut().or(root.right.as_deref_mut()).unwrap();
+        core::mem::swap(&mut root.key, &mut child.key);
+        assert!(matches!(unordered.validate(), Err(Error::InvariantViolation(_))));
+
+        let mut outranked = valid.clone();
+        let root = outranked.root.as_deref_mut().unwrap();
+        let child = root.left.as_deref_mut().or(root.right.as_deref_mut()).unwrap();
+        core::mem::swap(&mut root.priority, &mut child.priority);
+        assert!(matches!(outranked.validate(), Err(Error::InvariantViolation(_))));
+
+        let mut miscounted = valid.clone();
+        miscounted.len = 1;
+        assert!(matches!(miscounted.validate(), Err(Error::InvariantViolation(_))));
+
+        let mut missized = valid;
+        missized.root.as_deref_mut().unwrap().size = 3;
+        assert!(matches!(missized.validate(), Err(Error::InvariantViolation(_))));
+    }
+}
@@ -1,5 +1,23 @@
-use crate::{Node, NodeIter};
-use std::fmt;
+use crate::{Compare, Error, IntoIter, Natural, Node, NodeIter, Observer, Range, Rotation};
+use crate::iter::IntoNodes;
+use crate::node::{Listener, OptBoxNode};
+use crate::observer::BoxObserver;
+#[cfg(feature = "stats")]
+use crate::stats::{Counters, Stats};
+use crate::trace::Step;
+use core::borrow::Borrow;
+use alloc::collections::BTreeMap;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+use core::hash::{Hash, Hasher};
+use core::iter::{Iterator, FromIterator, IntoIterator};
+use core::ops::{Index, RangeBounds};
+use core::fmt;
+use core::cmp::Ordering;
+use core::cell::RefCell;
+use allocator_api2::alloc::{Allocator, Global};
+use allocator_api2::boxed::Box;
+use alloc::vec::Vec;
 
 // TODO: use configuration options to handle duplicates
 //      (a) put with duplicate key replaces old data
@@ -7,47 +25,343 @@ use std::fmt;
 //      (c) put with duplicate key keeps data versions (?)
 //      (d) ???
 
-type OptBoxNode<K,D> = Option<Box<Node<K,D>>>;
-
 // TODO: Entry API: https://doc.rust-lang.org/std/collections/#entries
-pub struct AVLTree<K,D> {
-    pub root: OptBoxNode<K,D>
+/// a map ordered by `C`, which defaults to the keys' `Ord` implementation, with nodes
+/// allocated by `A`, which defaults to the global allocator
+pub struct AVLTree<K,D,C = Natural,A: Allocator = Global> {
+    pub root: OptBoxNode<K,D,A>,
+    cmp: C,
+    alloc: A,
+    // number of nodes, kept up to date by the tree's own methods. changing `root` directly
+    // bypasses it
+    pub(crate) len: usize,
+    #[cfg(feature = "stats")]
+    stats: Counters,
+    observers: Vec<BoxObserver<K,D>>,
+    // steps recorded since `start_trace`, while recording
+    pub(crate) trace: Option<Vec<Step<K>>>,
+}
+
+/// a change to tell observers about, copied out of the tree so it can be delivered once
+/// the tree is whole again
+enum Event<K, D> {
+    Insert(K, D),
+    Replace(K, D, D),
+    Remove(K, D),
+    Rotate(Rotation, K, K),
+}
+
+/// the tree's comparator as the node algorithms see it: comparisons are forwarded to the
+/// comparator, and whatever the algorithms do is reported back to the tree
+struct Probe<'a, K, D, C> {
+    cmp: &'a C,
+    #[cfg(feature = "stats")]
+    stats: &'a Counters,
+    // changes for observers, held back until the operation is finished. `None` for lookups
+    // and for trees nobody observes
+    events: RefCell<Option<Vec<Event<K,D>>>>,
+    trace: RefCell<Option<&'a mut Vec<Step<K>>>>,
+}
+
+impl<K, D, C> Probe<'_, K, D, C> {
+    fn record<F: FnOnce() -> Step<K>>(&self, step: F) {
+        if let Some(trace) = self.trace.borrow_mut().as_mut() {
+            trace.push(step());
+        }
+    }
+
+    fn queue<F: FnOnce() -> Event<K,D>>(&self, event: F) {
+        if let Some(events) = self.events.borrow_mut().as_mut() {
+            events.push(event());
+        }
+    }
+
+    /// the events the operation produced, to be delivered once the tree is whole again
+    fn finish(self) -> Vec<Event<K,D>> {
+        return self.events.into_inner().unwrap_or_default();
+    }
+}
+
+impl<K, D, C, Q: ?Sized> Compare<Q> for Probe<'_, K, D, C> where C: Compare<Q> {
+    fn compare(&self, a: &Q, b: &Q) -> Ordering {
+        #[cfg(feature = "stats")]
+        self.stats.compared();
+        return self.cmp.compare(a, b);
+    }
+}
+
+impl<K: Clone, D: Clone, C> Listener<K,D> for Probe<'_, K, D, C> {
+    fn inserted(&self, key: &K, data: &D) {
+        self.record(|| Step::Insert { key: key.clone() });
+        self.queue(|| Event::Insert(key.clone(), data.clone()));
+    }
+
+    fn replaced(&self, key: &K, old: &D, new: &D) {
+        self.record(|| Step::Replace { key: key.clone() });
+        self.queue(|| Event::Replace(key.clone(), old.clone(), new.clone()));
+    }
+
+    fn removed(&self, key: &K, data: &D) {
+        self.record(|| Step::Remove { key: key.clone() });
+        self.queue(|| Event::Remove(key.clone(), data.clone()));
+    }
+
+    fn rebalanced(&self) {
+        #[cfg(feature = "stats")]
+        self.stats.rebalanced();
+    }
+
+    fn rotated(&self, rotation: Rotation, pivot: &K, promoted: &K, before: isize, after: isize) {
+        self.record(|| Step::Rotate {
+            rotation,
+            key: pivot.clone(),
+            promoted: promoted.clone(),
+            balance_before: before,
+            balance_after: after,
+        });
+        #[cfg(feature = "stats")]
+        self.stats.rotated(rotation);
+        self.queue(|| Event::Rotate(rotation, pivot.clone(), promoted.clone()));
+    }
 }
 
-impl <'a, K,D> AVLTree<K,D> 
-where K: Ord + Eq + Clone + fmt::Display + fmt::Debug, D: Ord + Eq + Clone + fmt::Display + fmt::Debug
+impl <K,D> AVLTree<K,D> 
+where K: Ord + Eq + Clone + fmt::Debug, D: Clone + fmt::Debug
 {
     pub fn new() -> Self {
-        Self {
-            root: None
-        }
+        Self::with_comparator(Natural)
     }
 
     pub fn with_root(root: Node<K,D>) -> Self {
         let mut tree = AVLTree::new();
-        tree.root = Some(Box::new(root));
+        // a hand-built tree can't be trusted to have its heights cached
+        tree.root = Some(Node::recompute_heights(Box::new(root)));
+        tree.len = tree.iter().count();
         return tree;
     }
 
-    pub fn iter(self: &'a Self) -> NodeIter<'a, K, D> {
+    /// build a perfectly balanced tree in O(n) from key/data pairs in strictly increasing
+    /// key order. the order is not checked; unsorted or duplicate keys produce a tree that
+    /// fails `validate`. see `try_from_sorted_iter` for a checked version
+    pub fn from_sorted_iter<I: IntoIterator<Item = (K,D)>>(iter: I) -> Self {
+        return Self::from_sorted_iter_by(iter, Natural);
+    }
+
+    /// like `from_sorted_iter`, but fails with `Error::Unsorted` or `Error::DuplicateKey` if
+    /// the keys are not strictly increasing
+    pub fn try_from_sorted_iter<I: IntoIterator<Item = (K,D)>>(iter: I) -> Result<Self, Error> {
+        return Self::try_from_sorted_iter_by(iter, Natural);
+    }
+}
+
+impl <K,D,A> AVLTree<K,D,Natural,A>
+where K: Ord + Eq + Clone + fmt::Debug, D: Clone + fmt::Debug, A: Allocator + Clone
+{
+    /// create an empty tree whose nodes are allocated by `alloc`
+    pub fn new_in(alloc: A) -> Self {
+        return Self::with_comparator_in(Natural, alloc);
+    }
+}
+
+impl <'a, K,D,C,A: Allocator> AVLTree<K,D,C,A> {
+    pub fn iter(&'a self) -> NodeIter<'a, K, D, A> {
         self.into_iter()       
     }
 
-    /// insert a new key/data pair into the tree
-    pub fn put(&mut self, key: K, data: D) -> bool {
-        if self.root.is_some() {
-            let root = self.root.take().expect("broken");
-            self.root = Some(root.put(key, data));
-        } else {
-            self.root = Some(Node::newbox(key, data));
+    /// number of key/data pairs in the tree, in O(1)
+    pub fn len(&self) -> usize {
+        return self.len;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len == 0;
+    }
+
+    /// remove every key/data pair
+    pub fn clear(&mut self) {
+        let nodes = IntoNodes::new(self.root.take());
+        // empty before anyone is told, in case an observer panics
+        self.len = 0;
+        for node in nodes {
+            self.removed(&node);
+            // the node is going anyway, so its key can move into the trace
+            if let Some(trace) = self.trace.as_mut() {
+                trace.push(Step::Remove { key: Box::into_inner(node).into_pair().0 });
+            }
+        }
+    }
+
+    /// register an observer to be told about every change the tree's own methods make from
+    /// now on. changing `root` directly bypasses observers. observers hear about a change
+    /// once the operation making it is finished, from copies of its keys and data, so an
+    /// observer that panics can't leave the tree half rebuilt
+    pub fn observe<O: Observer<K,D> + Send + Sync + 'static>(&mut self, observer: O) {
+        self.observers.push(alloc::boxed::Box::new(observer));
+    }
+
+    /// unregister every observer
+    pub fn clear_observers(&mut self) {
+        self.observers.clear();
+    }
+
+    /// whether any observer is registered or a trace is being recorded, so changes must go
+    /// through the node algorithms that report them
+    #[cfg(feature = "rayon")]
+    pub(crate) fn is_observed(&self) -> bool {
+        return !self.observers.is_empty() || self.trace.is_some();
+    }
+
+    /// a probe for lookups, which count comparisons but have nothing to tell observers
+    fn probe(&self) -> Probe<'_, K, D, C> {
+        return Probe {
+            cmp: &self.cmp,
+            #[cfg(feature = "stats")]
+            stats: &self.stats,
+            events: RefCell::new(None),
+            trace: RefCell::new(None),
+        };
+    }
+
+    /// a probe for operations that change the tree
+    fn probe_mut(&mut self) -> Probe<'_, K, D, C> {
+        return Probe {
+            cmp: &self.cmp,
+            #[cfg(feature = "stats")]
+            stats: &self.stats,
+            events: RefCell::new(if self.observers.is_empty() { None } else { Some(Vec::new()) }),
+            trace: RefCell::new(self.trace.as_mut()),
+        };
+    }
+
+    /// hand observers the events of a finished operation
+    fn deliver(&mut self, events: Vec<Event<K,D>>) {
+        for event in events {
+            for observer in self.observers.iter_mut() {
+                match &event {
+                    Event::Insert(key, data) => observer.on_insert(key, data),
+                    Event::Replace(key, old, new) => observer.on_replace(key, old, new),
+                    Event::Remove(key, data) => observer.on_remove(key, data),
+                    Event::Rotate(rotation, pivot, promoted) => observer.on_rotate(*rotation, pivot, promoted),
+                }
+            }
+        }
+    }
+
+    /// tell observers about a node removed outside the node algorithms
+    fn removed(&mut self, node: &Node<K,D,A>) {
+        for observer in self.observers.iter_mut() {
+            observer.on_remove(&node.key, &node.data);
         }
-        return true;
+    }
+
+    /// the allocator that holds this tree's nodes
+    pub fn allocator(&self) -> &A {
+        return &self.alloc;
+    }
+
+    /// record that the tree allocated `count` nodes
+    #[allow(unused_variables)]
+    pub(crate) fn allocated(&self, count: usize) {
+        #[cfg(feature = "stats")]
+        self.stats.allocated(count);
+    }
+
+    /// operation counts since the tree was created or `reset_stats` was last called
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> Stats {
+        return self.stats.snapshot();
+    }
+
+    /// zero the operation counts
+    #[cfg(feature = "stats")]
+    pub fn reset_stats(&self) {
+        self.stats.reset();
+    }
+}
+
+impl <K,D,C> AVLTree<K,D,C> 
+where K: Clone + fmt::Debug, D: Clone + fmt::Debug, C: Compare<K>
+{
+    /// create an empty tree whose keys are ordered by `cmp` rather than by `Ord`
+    pub fn with_comparator(cmp: C) -> Self {
+        return Self::with_comparator_in(cmp, Global);
+    }
+
+    /// `from_sorted_iter` for a tree ordered by `cmp`
+    pub fn from_sorted_iter_by<I: IntoIterator<Item = (K,D)>>(iter: I, cmp: C) -> Self {
+        return Self::from_sorted_iter_in(iter, cmp, Global);
+    }
+
+    /// `try_from_sorted_iter` for a tree ordered by `cmp`
+    pub fn try_from_sorted_iter_by<I: IntoIterator<Item = (K,D)>>(iter: I, cmp: C) -> Result<Self, Error> {
+        let items: Vec<(K,D)> = iter.into_iter().collect();
+        for pair in items.windows(2) {
+            match cmp.compare(&pair[0].0, &pair[1].0) {
+                Ordering::Less => (),
+                Ordering::Equal => return Err(Error::DuplicateKey),
+                Ordering::Greater => return Err(Error::Unsorted),
+            }
+        }
+        return Ok(Self::from_sorted_iter_by(items, cmp));
+    }
+
+    /// sort key/data pairs and build a balanced tree from them. when a key appears more than
+    /// once, the last pair wins, just as if the pairs had been `put` in order
+    fn from_unsorted_vec_by(mut items: Vec<(K,D)>, cmp: C) -> Self {
+        // stable sort, so duplicates stay in insertion order
+        items.sort_by(|a, b| cmp.compare(&a.0, &b.0));
+        let mut unique: Vec<(K,D)> = Vec::with_capacity(items.len());
+        for item in items {
+            match unique.last_mut() {
+                Some(last) if cmp.compare(&last.0, &item.0) == Ordering::Equal => *last = item,
+                _ => unique.push(item)
+            }
+        }
+        return Self::from_sorted_iter_by(unique, cmp);
+    }
+}
+
+impl <K,D,C,A> AVLTree<K,D,C,A> 
+where K: Clone + fmt::Debug, D: Clone + fmt::Debug, C: Compare<K>, A: Allocator + Clone
+{
+    /// `with_comparator` for a tree whose nodes are allocated by `alloc`
+    pub fn with_comparator_in(cmp: C, alloc: A) -> Self {
+        Self {
+            root: None,
+            cmp,
+            alloc,
+            len: 0,
+            #[cfg(feature = "stats")]
+            stats: Counters::default(),
+            observers: Vec::new(),
+            trace: None,
+        }
+    }
+
+    /// the comparator that orders this tree's keys
+    pub fn comparator(&self) -> &C {
+        return &self.cmp;
+    }
+
+    /// iterate in order over the key/data pairs whose keys fall within `range`
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Range<'_, K, D, C, A> {
+        #[cfg(feature = "stats")]
+        return Range::counted(self.root.as_deref(), range, &self.cmp, &self.stats);
+        #[cfg(not(feature = "stats"))]
+        return Range::with_comparator(self.root.as_deref(), range, &self.cmp);
+    }
+
+    /// insert a new key/data pair into the tree
+    /// if the key was already present, its data is replaced and the old data returned
+    pub fn put(&mut self, key: K, data: D) -> Option<D> {
+        self.allocated(1);
+        return self.ins(Node::newbox_in(key, data, self.alloc.clone())).map(|node| Box::into_inner(node).into_pair().1);
     }
 
     /// get a copy of the data associated with a given key
     pub fn get(&self, key: K) -> Option<D> {
         if let Some(root) = self.root.as_ref() {
-            if let Some(node) = root.get(key) {
+            if let Some(node) = root.get_by(&key, &self.probe()) {
                 return Some(node.data.clone());
             } else {
                 return None;
@@ -55,23 +369,164 @@ where K: Ord + Eq + Clone + fmt::Display + fmt::Debug, D: Ord + Eq + Clone + fmt
         } else { return None }
     }
 
-    /// delete the node specified by key
-    pub fn del(&mut self, key: K) -> bool {
+    /// the node holding the given key
+    pub(crate) fn find<Q>(&self, key: &Q) -> Option<&Node<K,D,A>>
+    where K: Borrow<Q>, Q: ?Sized, C: Compare<Q>
+    {
+        return self.root.as_ref()?.get_by(key, &self.probe());
+    }
+
+    /// the node holding the given key, for changing its data in place
+    pub(crate) fn find_mut<Q>(&mut self, key: &Q) -> Option<&mut Node<K,D,A>>
+    where K: Borrow<Q>, Q: ?Sized, C: Compare<Q>
+    {
+        // `probe` would borrow the whole tree, and the root is already borrowed mutably
+        let probe: Probe<K, D, C> = Probe {
+            cmp: &self.cmp,
+            #[cfg(feature = "stats")]
+            stats: &self.stats,
+            events: RefCell::new(None),
+            trace: RefCell::new(None),
+        };
+        return self.root.as_mut()?.get_mut_by(key, &probe);
+    }
+
+    /// the node holding the smallest key
+    pub(crate) fn first_node(&self) -> Option<&Node<K,D,A>> {
+        let mut node = self.root.as_deref()?;
+        while let Some(left) = node.left.as_deref() { node = left };
+        return Some(node);
+    }
+
+    /// the node holding the largest key
+    pub(crate) fn last_node(&self) -> Option<&Node<K,D,A>> {
+        let mut node = self.root.as_deref()?;
+        while let Some(right) = node.right.as_deref() { node = right };
+        return Some(node);
+    }
+
+    /// delete the node specified by key and return its data
+    pub fn del(&mut self, key: K) -> Result<D, Error> {
+        return self.remove_node(&key).map(|node| Box::into_inner(node).into_pair().1);
+    }
+
+    /// detach the node with the given key from the tree
+    pub(crate) fn remove_node<Q>(&mut self, key: &Q) -> Result<Box<Node<K,D,A>, A>, Error>
+    where K: Borrow<Q>, Q: ?Sized, C: Compare<Q>
+    {
         if let Some(root) = self.root.take() {
-            if let Ok(node) = root.del(key) {
-                self.root = node;
-                return true;
-            } else {
-                return false;
-            };
-        } else { return false }
+            let probe = self.probe_mut();
+            let (root, removed) = Node::del_in(root, key, &probe);
+            let events = probe.finish();
+            self.root = root;
+            if removed.is_ok() { self.len -= 1 }
+            self.deliver(events);
+            return removed;
+        } else { return Err(Error::KeyNotFound) }
     }
 
     /// insert an existing node without reallocating the memory
-    pub fn ins(&mut self, node: Box<Node<K,D>> ) {
+    /// if the key was already present, the node that held it is returned
+    pub fn ins(&mut self, node: Box<Node<K,D,A>, A>) -> Option<Box<Node<K,D,A>, A>> {
         if let Some(root) = self.root.take() {
-            self.root = Some(root.ins(node));
-        } else { self.root = Some(node) }
+            let probe = self.probe_mut();
+            let (root, replaced) = Node::ins_in(root, node, &probe);
+            let events = probe.finish();
+            self.root = Some(root);
+            if replaced.is_none() { self.len += 1 }
+            self.deliver(events);
+            return replaced;
+        } else {
+            let probe = self.probe_mut();
+            probe.inserted(&node.key, &node.data);
+            let events = probe.finish();
+            self.root = Some(node);
+            self.len = 1;
+            self.deliver(events);
+            return None;
+        }
+    }
+
+    /// insert an existing node, folding its data into the existing data with `merge` if the
+    /// key is already present. returns whether the key was new
+    pub(crate) fn ins_with<F: FnOnce(&mut D, D)>(&mut self, node: Box<Node<K,D,A>, A>, merge: F) -> bool {
+        let root = self.root.take();
+        let probe = self.probe_mut();
+        let (root, inserted) = match root {
+            Some(root) => Node::ins_with_in(root, node, &probe, merge),
+            None => {
+                probe.inserted(&node.key, &node.data);
+                (node, true)
+            }
+        };
+        let events = probe.finish();
+        self.root = Some(root);
+        if inserted { self.len += 1 }
+        self.deliver(events);
+        return inserted;
+    }
+
+    /// keep only the pairs for which `keep` returns true. the surviving nodes are relinked
+    /// into a balanced tree in a single O(n) pass, however many are removed
+    pub fn retain<F: FnMut(&K, &mut D) -> bool>(&mut self, mut keep: F) {
+        self.partition(|key, data| !keep(key, data));
+    }
+
+    /// remove the pairs for which `pred` returns true and return them in key order. the
+    /// pairs are removed up front in one O(n) pass, whether or not the iterator is consumed
+    pub fn extract_if<F: FnMut(&K, &mut D) -> bool>(&mut self, pred: F) -> alloc::vec::IntoIter<(K,D)> {
+        let removed: Vec<(K,D)> = self.partition(pred).into_iter()
+            .map(|node| Box::into_inner(node).into_pair())
+            .collect();
+        return removed.into_iter();
+    }
+
+    /// detach every node for which `pred` returns true and rebuild the tree from the rest.
+    /// if `pred` panics, the tree is rebuilt with every node it had
+    fn partition<F: FnMut(&K, &mut D) -> bool>(&mut self, mut pred: F) -> Vec<Box<Node<K,D,A>, A>> {
+        let seen = Vec::with_capacity(self.len);
+        let rest = IntoNodes::new(self.root.take());
+        let mut rebuild = Rebuild { root: &mut self.root, len: &mut self.len, seen, rest };
+        for node in rebuild.rest.by_ref() {
+            // held by the guard while `pred` runs, so a panic can't lose it
+            rebuild.seen.push((node, false));
+            let (node, remove) = rebuild.seen.last_mut().expect("just pushed");
+            let Node { key, data, .. } = &mut **node;
+            *remove = pred(key, data);
+        }
+        let (removed, kept): (Vec<_>, Vec<_>) = core::mem::take(&mut rebuild.seen).into_iter().partition(|(_, remove)| *remove);
+        rebuild.seen = kept;
+        drop(rebuild);
+        let removed: Vec<Box<Node<K,D,A>, A>> = removed.into_iter().map(|(node, _)| node).collect();
+        for node in removed.iter() {
+            if let Some(trace) = self.trace.as_mut() {
+                trace.push(Step::Remove { key: node.key.clone() });
+            }
+            self.removed(node);
+        }
+        return removed;
+    }
+
+    /// check that the tree satisfies the AVL invariants, and that `len` is its number of nodes
+    pub fn validate(&self) -> Result<(), Error> {
+        if let Some(root) = self.root.as_ref() {
+            root.validate_by(&self.cmp)?;
+        }
+        let count = self.iter().count();
+        if count != self.len {
+            return Err(Error::InvariantViolation(alloc::format!("{} nodes but a length of {}", count, self.len)));
+        }
+        return Ok(());
+    }
+
+    /// `from_sorted_iter_by` for a tree whose nodes are allocated by `alloc`
+    pub fn from_sorted_iter_in<I: IntoIterator<Item = (K,D)>>(iter: I, cmp: C, alloc: A) -> Self {
+        let items: Vec<(K,D)> = iter.into_iter().collect();
+        let mut tree = Self::with_comparator_in(cmp, alloc);
+        tree.len = items.len();
+        tree.root = Node::from_sorted_in(&mut items.into_iter(), tree.len, &tree.alloc);
+        tree.allocated(tree.len);
+        return tree;
     }
 
     /// return a vector of cloned key/value tuples
@@ -96,68 +551,224 @@ where K: Ord + Eq + Clone + fmt::Display + fmt::Debug, D: Ord + Eq + Clone + fmt
     }
     */
 
-    pub fn height(&mut self) -> usize {
-        if let Some(ref mut root) = self.root {
+    pub fn height(&self) -> usize {
+        if let Some(root) = &self.root {
             return root.height();
         } else { return 0 }
     }
 }
 
 impl<K,D> From <&Vec<(K,D)>> for AVLTree<K,D> 
-where K: Ord + Eq + Clone + fmt::Display + fmt::Debug, D: Ord + Eq + Clone + fmt::Display + fmt::Debug 
+where K: Ord + Eq + Clone + fmt::Debug, D: Clone + fmt::Debug 
 {
     fn from(nodes: &Vec<(K,D)>) -> AVLTree<K,D>{
-        let mut tree = AVLTree::new();
-        for node in nodes {
-            tree.put(node.0.clone(), node.1.clone());
-        }
-        return tree;
+        return AVLTree::from_unsorted_vec_by(nodes.clone(), Natural);
     }
 }
 
-use std::collections::HashMap;
-impl<K,D> From <&HashMap<K,D>> for AVLTree<K,D> 
-where K: Ord + Eq + Clone + fmt::Display + fmt::Debug, D: Ord + Eq + Clone + fmt::Display + fmt::Debug 
+#[cfg(feature = "std")]
+impl<K,D,S> From <&HashMap<K,D,S>> for AVLTree<K,D> 
+where K: Ord + Eq + Clone + fmt::Debug, D: Clone + fmt::Debug 
 {
-    fn from(nodes: &HashMap<K,D>) -> AVLTree<K,D>{
-        let mut tree = AVLTree::new();
-        for (key, data) in nodes.iter() {
-            tree.put(key.clone(), data.clone());
-        }
-        return tree;
+    fn from(nodes: &HashMap<K,D,S>) -> AVLTree<K,D>{
+        // keys in a map are already unique
+        let mut items: Vec<(K,D)> = nodes.iter().map(|(key, data)| (key.clone(), data.clone())).collect();
+        items.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        return AVLTree::from_sorted_iter(items);
     }
 }
 
-use std::iter::{Iterator, FromIterator, IntoIterator};
+impl<K,D,const N: usize> From <[(K,D); N]> for AVLTree<K,D> 
+where K: Ord + Eq + Clone + fmt::Debug, D: Clone + fmt::Debug 
+{
+    fn from(nodes: [(K,D); N]) -> AVLTree<K,D>{
+        return AVLTree::from_unsorted_vec_by(Vec::from(nodes), Natural);
+    }
+}
 
-impl <K,D> FromIterator <Node<K,D>> for AVLTree<K,D> 
-where K: Ord + Eq + Clone + fmt::Display + fmt::Debug, D: Ord + Eq + Clone + fmt::Display + fmt::Debug
+impl<K,D> From <BTreeMap<K,D>> for AVLTree<K,D> 
+where K: Ord + Eq + Clone + fmt::Debug, D: Clone + fmt::Debug 
+{
+    fn from(nodes: BTreeMap<K,D>) -> AVLTree<K,D>{
+        // a BTreeMap iterates in key order, so no sorting is needed
+        return AVLTree::from_sorted_iter(nodes);
+    }
+}
+
+impl<K: Ord, D, C, A: Allocator> From <AVLTree<K,D,C,A>> for BTreeMap<K,D> {
+    fn from(tree: AVLTree<K,D,C,A>) -> BTreeMap<K,D> {
+        return tree.into_iter().collect();
+    }
+}
+
+impl <K,D,C> FromIterator <Node<K,D>> for AVLTree<K,D,C> 
+where K: Clone + fmt::Debug, D: Clone + fmt::Debug, C: Compare<K> + Default
 {
     fn from_iter<I: IntoIterator<Item = Node<K,D>>>(iter: I) -> Self {
-        let mut tree = Self::new();
-        for i in iter {
-            tree.put(i.key, i.data);
+        return Self::from_unsorted_vec_by(iter.into_iter().map(Node::into_pair).collect(), C::default());
+    }
+}
+
+impl <K,D,C> FromIterator <(K,D)> for AVLTree<K,D,C> 
+where K: Clone + fmt::Debug, D: Clone + fmt::Debug, C: Compare<K> + Default
+{
+    fn from_iter<I: IntoIterator<Item = (K,D)>>(iter: I) -> Self {
+        return Self::from_unsorted_vec_by(iter.into_iter().collect(), C::default());
+    }
+}
+
+impl <K,D,C,A> Extend <(K,D)> for AVLTree<K,D,C,A> 
+where K: Clone + fmt::Debug, D: Clone + fmt::Debug, C: Compare<K>, A: Allocator + Clone
+{
+    fn extend<I: IntoIterator<Item = (K,D)>>(&mut self, iter: I) {
+        for (key, data) in iter {
+            self.put(key, data);
         }
-        return tree;
     }
 }
 
+impl <'a, K,D,C,A> Extend <(&'a K, &'a D)> for AVLTree<K,D,C,A> 
+where K: Copy + fmt::Debug, D: Copy + fmt::Debug, C: Compare<K>, A: Allocator + Clone
+{
+    fn extend<I: IntoIterator<Item = (&'a K, &'a D)>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().map(|(key, data)| (*key, *data)));
+    }
+}
 
-impl <'a, K, D> IntoIterator  for &'a AVLTree<K,D> 
-where K: Ord + Eq, D: Ord + Eq
+impl <K, D, C, A, Q> Index <&Q> for AVLTree<K,D,C,A>
+where K: Borrow<Q>, Q: ?Sized, C: Compare<Q>, K: fmt::Debug, D: fmt::Debug, A: Allocator + Clone
 {
+    type Output = D;
+
+    /// panics if the key is not in the tree
+    fn index(&self, key: &Q) -> &D {
+        return &self.root.as_ref()
+            .and_then(|root| root.get_by(key, &self.probe()))
+            .expect("key not found in tree")
+            .data;
+    }
+}
+
+impl <'a, K, D, C, A: Allocator> IntoIterator  for &'a AVLTree<K,D,C,A> {
     //type Item = &'a Node<K,D>;
     type Item = (&'a K, &'a D);
-    type IntoIter = NodeIter<'a, K, D>;
+    type IntoIter = NodeIter<'a, K, D, A>;
 
-    fn into_iter(self) -> NodeIter<'a, K, D> {
+    fn into_iter(self) -> NodeIter<'a, K, D, A> {
         if let Some(node) = &self.root {
-            return NodeIter::with_root(&node);
+            return NodeIter::with_root(node);
         } else { return NodeIter::new() }
     }
 }
 
-impl <K: fmt::Debug, D: fmt::Debug> fmt::Debug for AVLTree<K,D> {
+impl <K, D, C, A: Allocator> IntoIterator for AVLTree<K,D,C,A> {
+    type Item = (K, D);
+    type IntoIter = IntoIter<K, D, A>;
+
+    fn into_iter(mut self) -> IntoIter<K, D, A> {
+        return IntoIter::new(self.root.take());
+    }
+}
+
+type Judged<K,D,A> = (Box<Node<K,D,A>, A>, bool);
+
+/// the nodes of a tree being partitioned, which relinks whatever it holds into the tree when
+/// dropped: the nodes kept once partitioning is done, or every node if it was cut short
+struct Rebuild<'a, K: fmt::Debug, D: fmt::Debug, A: Allocator + Clone> {
+    root: &'a mut OptBoxNode<K,D,A>,
+    len: &'a mut usize,
+    // nodes already shown to the predicate, in order, each with whether to remove it
+    seen: Vec<Judged<K,D,A>>,
+    // nodes still to be shown to it, all after those in `seen`
+    rest: IntoNodes<K,D,A>,
+}
+
+impl<K: fmt::Debug, D: fmt::Debug, A: Allocator + Clone> Drop for Rebuild<'_, K, D, A> {
+    fn drop(&mut self) {
+        let mut nodes: Vec<Box<Node<K,D,A>, A>> = self.seen.drain(..).map(|(node, _)| node).collect();
+        nodes.extend(&mut self.rest);
+        *self.len = nodes.len();
+        *self.root = Node::from_sorted_nodes(&mut nodes.into_iter(), *self.len);
+    }
+}
+
+/// tear the tree down one node at a time instead of recursing through `Box` drops, so even
+/// a tree too deep to walk recursively can be dropped on a small stack
+impl <K, D, C, A: Allocator> Drop for AVLTree<K,D,C,A> {
+    fn drop(&mut self) {
+        drop(IntoNodes::new(self.root.take()));
+    }
+}
+
+impl <K: Clone, D: Clone, C: Clone, A: Allocator + Clone> Clone for AVLTree<K,D,C,A> {
+    /// the clone starts with no observers, no trace and fresh stats, counting the nodes it
+    /// copied as allocations
+    fn clone(&self) -> Self {
+        let tree = Self {
+            root: self.root.clone(),
+            cmp: self.cmp.clone(),
+            alloc: self.alloc.clone(),
+            len: self.len,
+            #[cfg(feature = "stats")]
+            stats: Counters::default(),
+            observers: Vec::new(),
+            trace: None,
+        };
+        tree.allocated(tree.len);
+        return tree;
+    }
+}
+
+impl <K, D, C: Default, A: Allocator + Default> Default for AVLTree<K,D,C,A> {
+    fn default() -> Self {
+        Self {
+            root: None,
+            cmp: C::default(),
+            alloc: A::default(),
+            len: 0,
+            #[cfg(feature = "stats")]
+            stats: Counters::default(),
+            observers: Vec::new(),
+            trace: None,
+        }
+    }
+}
+
+/* equality, ordering and hashing compare the in-order key/data sequence, not the shape of
+ * the tree, so two trees built by different insertion orders compare equal
+ */
+impl <K: PartialEq, D: PartialEq, C, A: Allocator> PartialEq for AVLTree<K,D,C,A> {
+    fn eq(&self, other: &Self) -> bool {
+        return self.iter().eq(other.iter());
+    }
+}
+
+impl <K: Eq, D: Eq, C, A: Allocator> Eq for AVLTree<K,D,C,A> {}
+
+impl <K: PartialOrd, D: PartialOrd, C, A: Allocator> PartialOrd for AVLTree<K,D,C,A> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        return self.iter().partial_cmp(other.iter());
+    }
+}
+
+impl <K: Ord, D: Ord, C, A: Allocator> Ord for AVLTree<K,D,C,A> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        return self.iter().cmp(other.iter());
+    }
+}
+
+impl <K: Hash, D: Hash, C, A: Allocator> Hash for AVLTree<K,D,C,A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // the length goes first, so a tree nested in a larger value can't run into whatever
+        // is hashed after it
+        self.len().hash(state);
+        for item in self.iter() {
+            item.hash(state);
+        }
+    }
+}
+
+impl <K: fmt::Debug, D: fmt::Debug, C, A: Allocator> fmt::Debug for AVLTree<K,D,C,A> {
     fn fmt( &self, formatter: &mut fmt::Formatter ) -> fmt::Result {
         if formatter.alternate() {
             // pretty print
@@ -176,8 +787,8 @@ mod tests {
     use std::collections::HashMap;
 
     fn test_put_set<K,D> (xs: HashMap<K, D>) 
-        where K: Ord + Eq + Clone + fmt::Display + fmt::Debug,
-              D: Ord + Eq + Clone + fmt::Display + fmt::Debug
+        where K: Ord + Eq + Clone + fmt::Debug,
+              D: Ord + Eq + Clone + fmt::Debug
     {
         let mut vec: Vec<(K,D)> = xs.iter().map(|(x,y)| (x.clone(),y.clone())).collect();
         let tree = AVLTree::from(&vec);
@@ -200,7 +811,417 @@ mod tests {
         test_put_set(xs);
     }
 
+    #[cfg(feature = "std")]
+    #[quickcheck]
+    fn qc_test_put_duplicate(xs: HashMap<isize, isize>, key: isize) {
+        let mut tree = AVLTree::from(&xs);
+        let mut expected = xs.clone();
+        assert_eq!(tree.put(key, 0), expected.insert(key, 0));
+
+        let mut vec: Vec<(isize,isize)> = expected.into_iter().collect();
+        vec.sort();
+        assert_eq!(tree.items(), vec);
+        assert_eq!(tree.validate(), Ok(()));
+    }
+
+    #[cfg(feature = "std")]
+    #[quickcheck]
+    fn qc_test_del_missing(xs: HashMap<isize, isize>, key: isize) {
+        if xs.contains_key(&key) { return }
+        let mut tree = AVLTree::from(&xs);
+        let before = tree.items();
+        assert_eq!(tree.del(key), Err(Error::KeyNotFound));
+        assert_eq!(tree.items(), before);
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut tree = AVLTree::new();
+        for i in 0..100 {
+            tree.put(i, i);
+            assert_eq!(tree.validate(), Ok(()));
+        }
+
+        // a chain of three nodes is out of balance
+        let mut root = Node::new(0, 0);
+        let mut right = Node::newbox(1, 1);
+        right.right = Some(Node::newbox(2, 2));
+        root.right = Some(right);
+        assert!(matches!(AVLTree::with_root(root).validate(), Err(Error::InvariantViolation(_))));
+
+        // keys out of order
+        let mut root = Node::new(1, 1);
+        root.left = Some(Node::newbox(2, 2));
+        assert!(matches!(AVLTree::with_root(root).validate(), Err(Error::InvariantViolation(_))));
+
+        // nodes swapped in behind the tree's back
+        tree.root = Some(Node::newbox(0, 0));
+        assert!(matches!(tree.validate(), Err(Error::InvariantViolation(_))));
+    }
+
+    #[quickcheck]
+    fn qc_test_from_sorted_iter(xs: HashMap<isize, isize>) {
+        let mut vec: Vec<(isize,isize)> = xs.into_iter().collect();
+        vec.sort();
+        let tree = AVLTree::from_sorted_iter(vec.clone());
+        assert_eq!(tree.validate(), Ok(()));
+        assert_eq!(tree.items(), vec);
+
+        // a perfectly balanced tree of n nodes has height ceil(log2(n + 1))
+        let n = vec.len();
+        let expected = (usize::BITS - n.leading_zeros()) as usize;
+        assert_eq!(tree.height(), expected);
+    }
+
+    #[test]
+    fn test_try_from_sorted_iter() {
+        let tree = AVLTree::try_from_sorted_iter(vec![(1, 1), (2, 2), (3, 3)]).unwrap();
+        assert_eq!(tree.items(), vec![(1, 1), (2, 2), (3, 3)]);
+
+        assert_eq!(AVLTree::try_from_sorted_iter(vec![(1, 1), (3, 3), (2, 2)]).err(), Some(Error::Unsorted));
+        assert_eq!(AVLTree::try_from_sorted_iter(vec![(1, 1), (2, 2), (2, 3)]).err(), Some(Error::DuplicateKey));
+    }
+
+    #[test]
+    fn test_from_vec_duplicates() {
+        // later pairs replace earlier ones, as with repeated puts
+        let tree = AVLTree::from(&vec![(2, 0), (1, 1), (2, 2), (3, 3), (1, 4)]);
+        assert_eq!(tree.items(), vec![(1, 4), (2, 2), (3, 3)]);
+        assert_eq!(tree.validate(), Ok(()));
+    }
+
+    #[quickcheck]
+    fn qc_test_reverse_comparator(xs: HashMap<isize, isize>, a: isize, b: isize) {
+        let mut tree = AVLTree::with_comparator(|a: &isize, b: &isize| b.cmp(a));
+        for (k, d) in xs.iter() {
+            tree.put(*k, *d);
+        }
+        assert_eq!(tree.validate(), Ok(()));
+
+        let mut vec: Vec<(isize,isize)> = xs.into_iter().collect();
+        vec.sort_by_key(|&(k, _)| std::cmp::Reverse(k));
+        assert_eq!(tree.items(), vec);
+
+        // bounds are interpreted in the tree's order: high..low
+        let (hi, lo) = if a >= b { (a, b) } else { (b, a) };
+        let expected: Vec<(isize,isize)> = vec.iter().cloned().filter(|(k, _)| *k <= hi && *k > lo).collect();
+        assert_eq!(tree.range(hi..lo).map(|(k, d)| (*k, *d)).collect::<Vec<_>>(), expected);
+
+        for (k, d) in vec {
+            assert_eq!(tree.get(k), Some(d));
+            assert_eq!(tree.del(k), Ok(d));
+            assert_eq!(tree.validate(), Ok(()));
+        }
+        assert!(tree.root.is_none());
+    }
+
+    #[test]
+    fn test_case_insensitive_comparator() {
+        let mut tree = AVLTree::with_comparator(|a: &String, b: &String| a.to_lowercase().cmp(&b.to_lowercase()));
+        tree.put(String::from("banana"), 1);
+        tree.put(String::from("Apple"), 2);
+        tree.put(String::from("cherry"), 3);
+        assert_eq!(tree.put(String::from("APPLE"), 4), Some(2));
+
+        assert_eq!(tree.get(String::from("apple")), Some(4));
+        let keys: Vec<&String> = tree.iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec!["APPLE", "banana", "cherry"]);
+        assert_eq!(tree.del(String::from("Banana")), Ok(1));
+        assert_eq!(tree.get(String::from("banana")), None);
+    }
+
+    #[test]
+    fn test_projected_comparator() {
+        #[derive(Clone, Debug, PartialEq)]
+        struct Event { at: u32, name: &'static str }
+
+        let items = vec![
+            (Event { at: 1, name: "start" }, ()),
+            (Event { at: 5, name: "stop" }, ()),
+            (Event { at: 3, name: "pause" }, ())];
+        let tree = AVLTree::try_from_sorted_iter_by(items.clone(), |a: &Event, b: &Event| a.at.cmp(&b.at));
+        assert_eq!(tree.err(), Some(Error::Unsorted));
+
+        let by_time = |a: &Event, b: &Event| a.at.cmp(&b.at);
+        let mut tree = AVLTree::with_comparator(by_time);
+        for (event, data) in items {
+            tree.put(event, data);
+        }
+        let probe = Event { at: 2, name: "" };
+        let names: Vec<&str> = tree.range(probe..).map(|(e, _)| e.name).collect();
+        assert_eq!(names, vec!["pause", "stop"]);
+    }
+
+    #[quickcheck]
+    fn qc_test_eq_ignores_shape(xs: HashMap<isize, isize>) {
+        let vec: Vec<(isize,isize)> = xs.into_iter().collect();
+        let balanced: AVLTree<isize,isize> = vec.iter().cloned().collect();
+        let mut inserted = AVLTree::new();
+        inserted.extend(vec.iter().map(|(k, d)| (k, d)));
+
+        assert_eq!(balanced, inserted);
+        assert_eq!(balanced.cmp(&inserted), Ordering::Equal);
+
+        let hash = |tree: &AVLTree<isize,isize>| {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            tree.hash(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash(&balanced), hash(&inserted));
+
+        // moving a pair from one tree to its neighbour leaves the items in the same order
+        if let Some((&key, &data)) = balanced.iter().next() {
+            let hash = |pair: &(AVLTree<isize,isize>, AVLTree<isize,isize>)| {
+                let mut hasher = std::collections::hash_map::DefaultHasher::new();
+                pair.hash(&mut hasher);
+                hasher.finish()
+            };
+            let mut rest = balanced.clone();
+            rest.del(key).unwrap();
+            let first: AVLTree<isize,isize> = core::iter::once((key, data)).collect();
+            assert_ne!(hash(&(first, rest.clone())), hash(&(AVLTree::new(), balanced.clone())));
+        }
+
+        let cloned = balanced.clone();
+        assert_eq!(cloned, balanced);
+        assert_eq!(cloned.validate(), Ok(()));
+    }
+
+    #[quickcheck]
+    fn qc_test_btreemap_roundtrip(xs: BTreeMap<isize, String>) {
+        let tree = AVLTree::from(xs.clone());
+        assert_eq!(tree.validate(), Ok(()));
+        for (k, d) in xs.iter() {
+            assert_eq!(&tree[k], d);
+        }
+        let back: BTreeMap<isize, String> = tree.into();
+        assert_eq!(back, xs);
+    }
+
+    #[quickcheck]
+    fn qc_test_ord_matches_btreemap(xs: BTreeMap<u8, u8>, ys: BTreeMap<u8, u8>) {
+        let (a, b) = (AVLTree::from(xs.clone()), AVLTree::from(ys.clone()));
+        assert_eq!(a.cmp(&b), xs.cmp(&ys));
+        assert_eq!(a == b, xs == ys);
+    }
+
+    #[test]
+    fn test_std_traits() {
+        let tree = AVLTree::from([(3, "c"), (1, "a"), (2, "b")]);
+        assert_eq!(tree.items(), vec![(1, "a"), (2, "b"), (3, "c")]);
+        assert_eq!(tree[&2], "b");
+
+        let empty: AVLTree<String, isize> = AVLTree::default();
+        assert!(empty.root.is_none());
+
+        // borrowed lookups: String keys indexed by &str
+        let mut names: AVLTree<String, usize> = AVLTree::default();
+        names.extend(vec![(String::from("one"), 1), (String::from("two"), 2)]);
+        assert_eq!(names["two"], 2);
+
+        let owned: Vec<(String, usize)> = names.into_iter().collect();
+        assert_eq!(owned, vec![(String::from("one"), 1), (String::from("two"), 2)]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_index_missing() {
+        let tree = AVLTree::from([(1, 1)]);
+        let _ = tree[&2];
+    }
+
+    #[quickcheck]
+    fn qc_test_len(puts: Vec<(u8, u8)>, dels: Vec<u8>) {
+        let mut tree = AVLTree::new();
+        let mut model = BTreeMap::new();
+        for (k, d) in puts {
+            tree.put(k, d);
+            model.insert(k, d);
+            assert_eq!(tree.len(), model.len());
+        }
+        for k in dels {
+            assert_eq!(tree.del(k).ok(), model.remove(&k));
+            assert_eq!(tree.len(), model.len());
+        }
+        assert_eq!(tree.is_empty(), model.is_empty());
+        assert_eq!(AVLTree::from(model.clone()).len(), model.len());
+
+        tree.clear();
+        assert_eq!(tree.len(), 0);
+        assert!(tree.root.is_none());
+    }
+
+    #[quickcheck]
+    fn qc_test_retain(xs: BTreeMap<isize, isize>) {
+        let mut tree = AVLTree::from(xs.clone());
+        tree.retain(|k, d| {
+            *d = d.wrapping_add(1);
+            k % 3 != 0
+        });
+        let expected: Vec<(isize,isize)> = xs.iter()
+            .filter(|(k, _)| *k % 3 != 0)
+            .map(|(k, d)| (*k, d.wrapping_add(1)))
+            .collect();
+        assert_eq!(tree.items(), expected);
+        assert_eq!(tree.len(), expected.len());
+        assert_eq!(tree.validate(), Ok(()));
+    }
+
+    #[quickcheck]
+    fn qc_test_retain_panicking(xs: BTreeMap<isize, isize>, at: usize) {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        // the predicate panics after `limit` pairs, having removed some of them, unless the
+        // tree runs out first
+        let mut tree = AVLTree::from(xs.clone());
+        let limit = at % (xs.len() + 1);
+        let mut seen = 0;
+        let result = catch_unwind(AssertUnwindSafe(|| tree.retain(|k, _| {
+            if seen == limit { panic!("predicate panicked") }
+            seen += 1;
+            k % 2 == 0
+        })));
+        assert_eq!(result.is_err(), limit < xs.len());
+        if result.is_err() {
+            // nothing is lost
+            assert_eq!(BTreeMap::from(tree.clone()), xs);
+        }
+        assert_eq!(tree.validate(), Ok(()));
+    }
+
+    #[quickcheck]
+    fn qc_test_extract_if(xs: BTreeMap<isize, isize>) {
+        let mut tree = AVLTree::from(xs.clone());
+        let extracted: Vec<(isize,isize)> = tree.extract_if(|_, d| *d > 0).collect();
+        let (expected_out, expected_in): (Vec<_>, Vec<_>) = xs.into_iter().partition(|(_, d)| *d > 0);
+        assert_eq!(extracted, expected_out);
+        assert_eq!(tree.items(), expected_in);
+        assert_eq!(tree.len(), expected_in.len());
+        assert_eq!(tree.validate(), Ok(()));
+    }
+
+    /* the deep-tree tests run on a thread with a small stack, so any operation that recurses
+     * once per level of the tree overflows instead of passing
+     */
+    const DEEP: u32 = 10_000_000;
+    const SMALL_STACK: usize = 64 * 1024;
+
+    fn on_small_stack<F: FnOnce() + Send + 'static>(f: F) {
+        std::thread::Builder::new()
+            .stack_size(SMALL_STACK)
+            .spawn(f)
+            .expect("spawn thread")
+            .join()
+            .expect("thread panicked");
+    }
+
+    #[test]
+    fn test_degenerate_chain_on_small_stack() {
+        on_small_stack(|| {
+            // a right-leaning chain, as deep as it is long
+            let mut root = Node::new(DEEP - 1, ());
+            for key in (0..DEEP - 1).rev() {
+                let mut node = Node::new(key, ());
+                node.right = Some(Box::new(root));
+                root = node;
+            }
+            let mut tree = AVLTree::with_root(root);
+            assert_eq!(tree.len(), DEEP as usize);
+            assert_eq!(tree.height(), DEEP as usize);
+            assert!(matches!(tree.validate(), Err(Error::InvariantViolation(_))));
+
+            assert_eq!(tree.get(DEEP - 1), Some(()));
+            assert_eq!(tree.get(DEEP), None);
+            assert_eq!(tree.put(DEEP, ()), None);
+            assert_eq!(tree.del(DEEP / 2), Ok(()));
+            assert_eq!(tree.del(DEEP / 2), Err(Error::KeyNotFound));
+            assert_eq!(tree.len(), DEEP as usize);
+
+            let copy = tree.clone();
+            assert_eq!(copy.height(), tree.height());
+            assert!(copy.iter().eq(tree.iter()));
+        });
+    }
+
+    #[test]
+    fn test_bare_chain_on_small_stack() {
+        on_small_stack(|| {
+            // nodes linked by hand, with no tree around them to tear them down
+            let mut root = Node::new(0, ());
+            for key in 1..DEEP {
+                let mut node = Node::new(key, ());
+                node.left = Some(Box::new(root));
+                root = node;
+            }
+            let copy = root.clone();
+            assert_eq!(copy.key, DEEP - 1);
+            assert_eq!(copy.into_pair(), (DEEP - 1, ()));
+            drop(root);
+        });
+    }
+
+    #[test]
+    fn test_large_tree_on_small_stack() {
+        on_small_stack(|| {
+            let mut tree = AVLTree::from_sorted_iter((0..DEEP).map(|key| (key * 2, ())));
+            for key in 0..1000 {
+                assert_eq!(tree.put(key * 2 + 1, ()), None);
+                assert_eq!(tree.del(key * 4), Ok(()));
+            }
+            assert_eq!(tree.len(), DEEP as usize);
+            assert_eq!(tree.validate(), Ok(()));
+            assert_eq!(tree.iter().count(), DEEP as usize);
+        });
+    }
+
+    /// forwards to the global allocator, counting the blocks it holds
+    #[derive(Clone, Default)]
+    struct Tracking(std::sync::Arc<std::sync::atomic::AtomicIsize>);
+
+    impl Tracking {
+        fn live(&self) -> isize {
+            return self.0.load(std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    unsafe impl Allocator for Tracking {
+        fn allocate(&self, layout: core::alloc::Layout) -> Result<core::ptr::NonNull<[u8]>, allocator_api2::alloc::AllocError> {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            return Global.allocate(layout);
+        }
+
+        unsafe fn deallocate(&self, ptr: core::ptr::NonNull<u8>, layout: core::alloc::Layout) {
+            self.0.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+            unsafe { Global.deallocate(ptr, layout) }
+        }
+    }
+
+    #[test]
+    fn test_allocator() {
+        let alloc = Tracking::default();
+        let mut tree = AVLTree::new_in(alloc.clone());
+        for key in 0..100 { tree.put(key, key); }
+        assert_eq!(alloc.live(), 100);
+
+        assert_eq!(tree.put(7, 70), Some(7));
+        assert_eq!(tree.del(9), Ok(9));
+        tree.retain(|key, _| key % 2 == 0);
+        assert_eq!(alloc.live(), 50);
+        assert_eq!(tree.validate(), Ok(()));
+
+        let copy = tree.clone();
+        assert_eq!(alloc.live(), 100);
+        drop(tree);
+        assert_eq!(copy.into_iter().count(), 50);
+        assert_eq!(alloc.live(), 0);
+
+        let tree = AVLTree::from_sorted_iter_in((0..10).map(|key| (key, ())), Natural, alloc.clone());
+        assert_eq!(alloc.live(), 10);
+        assert_eq!(tree.range(3..5).count(), 2);
+        drop(tree);
+        assert_eq!(alloc.live(), 0);
+    }
+
     // TODO: test get
-    // TODO: test del
     // TODO: test merge
 }
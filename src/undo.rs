@@ -0,0 +1,283 @@
+use crate::{AVLTree, Compare, Error, Natural, Node};
+use allocator_api2::alloc::{Allocator, Global};
+use allocator_api2::boxed::Box;
+use core::fmt;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// the data a key held before and after one change. `None` means the key was absent, so
+/// undoing and redoing are the same operation in opposite directions
+struct Edit<K, D> {
+    key: K,
+    before: Option<D>,
+    after: Option<D>,
+}
+
+/// an `AVLTree` that records every change made through it, so changes can be undone and
+/// redone one at a time or back to a named checkpoint
+pub struct UndoableTree<K, D, C = Natural, A: Allocator = Global> {
+    tree: AVLTree<K,D,C,A>,
+    undo: VecDeque<Edit<K,D>>,
+    redo: Vec<Edit<K,D>>,
+    // edits discarded from the front of `undo` to stay within `limit`
+    discarded: usize,
+    limit: usize,
+    // each checkpoint is the number of edits, counting discarded ones, made before it was set
+    checkpoints: BTreeMap<String, usize>,
+}
+
+impl<K, D> UndoableTree<K, D>
+where K: Ord + Eq + Clone + fmt::Debug, D: Clone + fmt::Debug
+{
+    pub fn new() -> Self {
+        return Self::with_tree(AVLTree::new());
+    }
+}
+
+impl<K, D> Default for UndoableTree<K, D>
+where K: Ord + Eq + Clone + fmt::Debug, D: Clone + fmt::Debug
+{
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl<K, D, C, A> UndoableTree<K, D, C, A>
+where K: Clone + fmt::Debug, D: Clone + fmt::Debug, C: Compare<K>, A: Allocator + Clone
+{
+    /// start recording changes to an existing tree, with no limit on the number of edits kept
+    pub fn with_tree(tree: AVLTree<K,D,C,A>) -> Self {
+        return Self {
+            tree,
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            discarded: 0,
+            limit: usize::MAX,
+            checkpoints: BTreeMap::new(),
+        };
+    }
+
+    /// keep at most `limit` edits to undo, discarding the oldest first. the limit counts
+    /// edits, not bytes: each edit is one key's change and holds the key and up to two copies
+    /// of its data, however large those are. checkpoints that need a discarded edit can no
+    /// longer be reverted to
+    pub fn set_edit_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        self.trim();
+    }
+
+    pub fn tree(&self) -> &AVLTree<K,D,C,A> {
+        return &self.tree;
+    }
+
+    /// stop recording and return the tree as it stands
+    pub fn into_inner(self) -> AVLTree<K,D,C,A> {
+        return self.tree;
+    }
+
+    pub fn get(&self, key: K) -> Option<D> {
+        return self.tree.get(key);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.tree.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.tree.is_empty();
+    }
+
+    /// `AVLTree::put`, recorded
+    pub fn put(&mut self, key: K, data: D) -> Option<D> {
+        let old = self.tree.put(key.clone(), data.clone());
+        self.record(Edit { key, before: old.clone(), after: Some(data) });
+        return old;
+    }
+
+    /// `AVLTree::del`, recorded if the key was present
+    pub fn del(&mut self, key: K) -> Result<D, Error> {
+        let old = self.tree.del(key.clone())?;
+        self.record(Edit { key, before: Some(old.clone()), after: None });
+        return Ok(old);
+    }
+
+    /// `AVLTree::ins`, recorded
+    pub fn ins(&mut self, node: Box<Node<K,D,A>, A>) -> Option<Box<Node<K,D,A>, A>> {
+        let (key, after) = (node.key.clone(), node.data.clone());
+        let replaced = self.tree.ins(node);
+        let before = replaced.as_ref().map(|node| node.data.clone());
+        self.record(Edit { key, before, after: Some(after) });
+        return replaced;
+    }
+
+    /// revert the most recent change. returns false if there is nothing left to undo
+    pub fn undo(&mut self) -> bool {
+        let Some(edit) = self.undo.pop_back() else { return false };
+        self.set(edit.key.clone(), edit.before.clone());
+        self.redo.push(edit);
+        return true;
+    }
+
+    /// reapply the most recently undone change. returns false if there is nothing to redo
+    pub fn redo(&mut self) -> bool {
+        let Some(edit) = self.redo.pop() else { return false };
+        self.set(edit.key.clone(), edit.after.clone());
+        self.undo.push_back(edit);
+        return true;
+    }
+
+    /// name the tree as it stands, replacing any earlier checkpoint of the same name
+    pub fn checkpoint(&mut self, name: &str) {
+        self.checkpoints.insert(String::from(name), self.position());
+    }
+
+    /// undo or redo changes until the tree is as it was when `name` was set
+    pub fn revert_to(&mut self, name: &str) -> Result<(), Error> {
+        let target = *self.checkpoints.get(name)
+            .filter(|target| **target >= self.discarded)
+            .ok_or_else(|| Error::CheckpointUnavailable(String::from(name)))?;
+        while self.position() > target { self.undo(); }
+        while self.position() < target { self.redo(); }
+        return Ok(());
+    }
+
+    /// number of edits made since recording began, counting discarded ones but not undone ones
+    fn position(&self) -> usize {
+        return self.discarded + self.undo.len();
+    }
+
+    fn set(&mut self, key: K, data: Option<D>) {
+        match data {
+            Some(data) => { self.tree.put(key, data); }
+            None => { self.tree.del(key).expect("history out of step with tree"); }
+        }
+    }
+
+    /// push a new edit. whatever was undone can't be redone after it, and checkpoints
+    /// that were only reachable by redoing are dropped
+    fn record(&mut self, edit: Edit<K,D>) {
+        self.redo.clear();
+        let position = self.position();
+        self.checkpoints.retain(|_, target| *target <= position);
+        self.undo.push_back(edit);
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        while self.undo.len() > self.limit {
+            self.undo.pop_front();
+            self.discarded += 1;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_undo_redo() {
+        let mut tree = UndoableTree::new();
+        tree.put(1, "a");
+        tree.put(2, "b");
+        assert_eq!(tree.put(1, "c"), Some("a"));
+        assert_eq!(tree.del(2), Ok("b"));
+        assert_eq!(tree.del(2), Err(Error::KeyNotFound));
+        assert_eq!(tree.ins(Node::newbox(3, "d")), None);
+
+        assert!(tree.undo());
+        assert!(tree.undo());
+        assert_eq!(tree.get(2), Some("b"));
+        // undoing a replacement brings back the replaced data rather than deleting the key
+        assert!(tree.undo());
+        assert_eq!(tree.get(1), Some("a"));
+        assert!(tree.redo());
+        assert_eq!(tree.get(1), Some("c"));
+
+        // a new change discards what was left to redo
+        tree.put(4, "e");
+        assert!(!tree.redo());
+        assert_eq!(tree.tree().items(), vec![(1, "c"), (2, "b"), (4, "e")]);
+
+        while tree.undo() {}
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn test_checkpoints() {
+        let mut tree = UndoableTree::new();
+        tree.put(1, 1);
+        tree.checkpoint("one");
+        tree.put(2, 2);
+        tree.put(3, 3);
+        tree.checkpoint("three");
+        tree.del(1).unwrap();
+
+        assert_eq!(tree.revert_to("one"), Ok(()));
+        assert_eq!(tree.tree().items(), vec![(1, 1)]);
+        assert_eq!(tree.revert_to("three"), Ok(()));
+        assert_eq!(tree.tree().items(), vec![(1, 1), (2, 2), (3, 3)]);
+        assert_eq!(tree.revert_to("nowhere"), Err(Error::CheckpointUnavailable(String::from("nowhere"))));
+
+        // "three" is only reachable by redoing, so a new change drops it
+        tree.revert_to("one").unwrap();
+        tree.put(5, 5);
+        assert!(tree.revert_to("three").is_err());
+
+        // and a checkpoint behind the edit limit can't be reached either
+        tree.set_edit_limit(1);
+        tree.put(6, 6);
+        assert!(tree.revert_to("one").is_err());
+        assert!(tree.undo());
+        assert!(!tree.undo());
+        assert_eq!(tree.tree().items(), vec![(1, 1), (5, 5)]);
+    }
+
+    #[test]
+    fn test_edit_limit_counts_edits() {
+        let mut tree = UndoableTree::new();
+        tree.set_edit_limit(2);
+        // one edit however much data it holds, so it takes one slot like any other
+        tree.put(0, vec![0u8; 1 << 20]);
+        tree.put(1, vec![1]);
+        assert!(tree.undo());
+        assert!(tree.redo());
+
+        // a third edit pushes out the large one, and its memory with it
+        tree.put(2, vec![2]);
+        assert!(tree.undo());
+        assert!(tree.undo());
+        assert!(!tree.undo());
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree.get(0).map(|data| data.len()), Some(1 << 20));
+    }
+
+    #[quickcheck]
+    fn qc_test_undo_all(xs: BTreeMap<u8, u8>, ops: Vec<(bool, u8, u8)>, undos: usize) {
+        let mut tree = UndoableTree::with_tree(AVLTree::from_sorted_iter(xs.clone()));
+        let mut snapshots = vec![xs.clone()];
+        for (put, key, data) in ops {
+            let changed = if put { tree.put(key, data); true } else { tree.del(key).is_ok() };
+            if changed { snapshots.push(BTreeMap::from(tree.tree().clone())); }
+        }
+
+        // undo part of the way, check every step, then redo back to the end
+        let undos = undos % snapshots.len();
+        for expected in snapshots.iter().rev().skip(1).take(undos) {
+            assert!(tree.undo());
+            assert_eq!(&BTreeMap::from(tree.tree().clone()), expected);
+            assert_eq!(tree.tree().validate(), Ok(()));
+        }
+        for expected in snapshots.iter().skip(snapshots.len() - undos) {
+            assert!(tree.redo());
+            assert_eq!(&BTreeMap::from(tree.tree().clone()), expected);
+        }
+        assert!(!tree.redo());
+
+        while tree.undo() {}
+        assert_eq!(BTreeMap::from(tree.into_inner()), xs);
+    }
+}
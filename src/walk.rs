@@ -0,0 +1,320 @@
+use crate::{Compare, IterType};
+use core::cmp::Ordering::{Greater, Less};
+use core::ops::{Bound, RangeBounds};
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+/* the alternative trees, `RBTree`, `SplayTree` and `Treap`, each have their own node type
+ * but share one way of walking it. the trait is public only so their iterator aliases can
+ * name it; the module is private, so nothing outside the crate can implement it
+ */
+
+type Child<N> = Option<Box<N>>;
+type Pair<N> = (<N as Branch>::Key, <N as Branch>::Data);
+
+/// a node with a key/data pair and up to two boxed children
+pub trait Branch: Sized {
+    type Key;
+    type Data;
+
+    fn pair(&self) -> (&Self::Key, &Self::Data);
+    fn left(&self) -> Option<&Self>;
+    fn right(&self) -> Option<&Self>;
+    /// take the node apart into its pair and its left and right children
+    fn unlink(self: Box<Self>) -> (Pair<Self>, Child<Self>, Child<Self>);
+}
+
+/// iterates over a tree's key/data pairs in any of the `IterType` orders, in order by default
+pub struct Walk<'a, N> {
+    root: Option<&'a N>,
+    // nodes still to visit, each with whether its children have been queued yet
+    pending: VecDeque<(&'a N, bool)>,
+    itype: IterType,
+}
+
+impl<'a, N: Branch> Walk<'a, N> {
+    pub(crate) fn new(root: Option<&'a N>, itype: IterType) -> Self {
+        let mut iter = Walk { root, pending: VecDeque::new(), itype };
+        match iter.itype {
+            IterType::DFInOrder => iter.push_spine(root, false),
+            IterType::DFInOrderReverse => iter.push_spine(root, true),
+            _ => iter.pending.extend(root.map(|root| (root, false)))
+        }
+        return iter;
+    }
+
+    /// restart the iteration from the root in another order
+    pub fn with_type(self, itype: IterType) -> Self {
+        return Self::new(self.root, itype);
+    }
+
+    /// queue a node and its chain of left children, or right children if `reverse`
+    fn push_spine(&mut self, mut curr: Option<&'a N>, reverse: bool) {
+        while let Some(node) = curr {
+            self.pending.push_back((node, true));
+            curr = if reverse { node.right() } else { node.left() };
+        }
+    }
+}
+
+impl<'a, N: Branch> Iterator for Walk<'a, N> {
+    type Item = (&'a N::Key, &'a N::Data);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = match self.itype {
+            IterType::DFInOrder => {
+                let (node, _) = self.pending.pop_back()?;
+                self.push_spine(node.right(), false);
+                node
+            }
+            IterType::DFInOrderReverse => {
+                let (node, _) = self.pending.pop_back()?;
+                self.push_spine(node.left(), true);
+                node
+            }
+            IterType::DFPreOrder => {
+                let (node, _) = self.pending.pop_back()?;
+                for child in [node.right(), node.left()].iter().flatten() {
+                    self.pending.push_back((child, false));
+                }
+                node
+            }
+            IterType::DFPostOrder => loop {
+                // a node is visited the second time it comes off the stack, after both
+                // of its subtrees
+                let (node, expanded) = self.pending.pop_back()?;
+                if expanded { break node }
+                self.pending.push_back((node, true));
+                for child in [node.right(), node.left()].iter().flatten() {
+                    self.pending.push_back((child, false));
+                }
+            },
+            IterType::BF => {
+                let (node, _) = self.pending.pop_front()?;
+                for child in [node.left(), node.right()].iter().flatten() {
+                    self.pending.push_back((child, false));
+                }
+                node
+            }
+        };
+        return Some(node.pair());
+    }
+}
+
+/// iterates in order over the pairs of a tree whose keys fall within a range
+pub struct Span<'a, N: Branch, C> {
+    stack: Vec<&'a N>,
+    upper: Bound<N::Key>,
+    cmp: &'a C,
+}
+
+impl<'a, N: Branch, C: Compare<N::Key>> Span<'a, N, C> where N::Key: Clone {
+    pub(crate) fn new<R: RangeBounds<N::Key>>(root: Option<&'a N>, range: R, cmp: &'a C) -> Self {
+        let mut stack = Vec::new();
+        // walk down to the lower bound, stacking every node at or above it on the way
+        let mut curr = root;
+        while let Some(node) = curr {
+            let key = node.pair().0;
+            let above_lower = match range.start_bound() {
+                Bound::Included(start) => cmp.compare(key, start) != Less,
+                Bound::Excluded(start) => cmp.compare(key, start) == Greater,
+                Bound::Unbounded => true
+            };
+            if above_lower {
+                stack.push(node);
+                curr = node.left();
+            } else {
+                curr = node.right();
+            }
+        }
+        return Span { stack, upper: range.end_bound().cloned(), cmp };
+    }
+}
+
+impl<'a, N: Branch, C: Compare<N::Key>> Iterator for Span<'a, N, C> {
+    type Item = (&'a N::Key, &'a N::Data);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        let (key, data) = node.pair();
+        let below_upper = match &self.upper {
+            Bound::Included(end) => self.cmp.compare(key, end) != Greater,
+            Bound::Excluded(end) => self.cmp.compare(key, end) == Less,
+            Bound::Unbounded => true
+        };
+        if !below_upper {
+            // everything left on the stack is even further past the upper bound
+            self.stack.clear();
+            return None;
+        }
+
+        // the next key is the left-most node of the right subtree
+        let mut curr = node.right();
+        while let Some(next) = curr {
+            self.stack.push(next);
+            curr = next.left();
+        }
+        return Some((key, data));
+    }
+}
+
+/// owning in-order iterator over a tree's key/data pairs
+pub struct Drain<N: Branch> {
+    // each node's left child has already been taken and queued above it
+    stack: Vec<(Pair<N>, Child<N>)>,
+}
+
+impl<N: Branch> Drain<N> {
+    pub(crate) fn new(root: Child<N>) -> Self {
+        let mut iter = Drain { stack: Vec::new() };
+        iter.push_left_spine(root);
+        return iter;
+    }
+
+    fn push_left_spine(&mut self, mut curr: Child<N>) {
+        while let Some(node) = curr {
+            let (pair, left, right) = node.unlink();
+            self.stack.push((pair, right));
+            curr = left;
+        }
+    }
+}
+
+impl<N: Branch> Iterator for Drain<N> {
+    type Item = Pair<N>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (pair, right) = self.stack.pop()?;
+        self.push_left_spine(right);
+        return Some(pair);
+    }
+}
+
+impl<N: Branch> Drop for Drain<N> {
+    fn drop(&mut self) {
+        // take apart whatever is left one node at a time, rather than dropping subtrees that
+        // may be too deep to drop recursively
+        for _ in self.by_ref() {}
+    }
+}
+
+/* the conversions and std traits every alternative tree implements the same way. `$tree`
+ * must have `with_comparator`, `put`, `iter` and `len`, and a `find` that looks a key up
+ * through `&self`. equality, ordering and hashing compare the in-order key/data sequence,
+ * as they do for `AVLTree`, not the shape of the tree
+ */
+macro_rules! map_traits {
+    ($tree:ident) => {
+        impl<K, D> From<&::alloc::vec::Vec<(K,D)>> for $tree<K, D>
+        where K: Ord + Eq + Clone + ::core::fmt::Debug, D: Clone + ::core::fmt::Debug
+        {
+            fn from(nodes: &::alloc::vec::Vec<(K,D)>) -> $tree<K, D> {
+                return nodes.iter().cloned().collect();
+            }
+        }
+
+        #[cfg(feature = "std")]
+        impl<K, D, S> From<&::std::collections::HashMap<K,D,S>> for $tree<K, D>
+        where K: Ord + Eq + Clone + ::core::fmt::Debug, D: Clone + ::core::fmt::Debug
+        {
+            fn from(nodes: &::std::collections::HashMap<K,D,S>) -> $tree<K, D> {
+                return nodes.iter().map(|(key, data)| (key.clone(), data.clone())).collect();
+            }
+        }
+
+        impl<K, D, const N: usize> From<[(K,D); N]> for $tree<K, D>
+        where K: Ord + Eq + Clone + ::core::fmt::Debug, D: Clone + ::core::fmt::Debug
+        {
+            fn from(nodes: [(K,D); N]) -> $tree<K, D> {
+                return ::alloc::vec::Vec::from(nodes).into_iter().collect();
+            }
+        }
+
+        impl<K: Ord, D, C> From<$tree<K, D, C>> for ::alloc::collections::BTreeMap<K, D> {
+            fn from(tree: $tree<K, D, C>) -> ::alloc::collections::BTreeMap<K, D> {
+                return tree.into_iter().collect();
+            }
+        }
+
+        impl<K, D, C> ::core::iter::FromIterator<(K,D)> for $tree<K, D, C>
+        where K: Clone + ::core::fmt::Debug, D: Clone + ::core::fmt::Debug, C: $crate::Compare<K> + Default
+        {
+            /// when a key appears more than once, the last pair wins
+            fn from_iter<I: IntoIterator<Item = (K,D)>>(iter: I) -> Self {
+                let mut tree = Self::with_comparator(C::default());
+                tree.extend(iter);
+                return tree;
+            }
+        }
+
+        impl<K, D, C> Extend<(K,D)> for $tree<K, D, C>
+        where K: Clone + ::core::fmt::Debug, D: Clone + ::core::fmt::Debug, C: $crate::Compare<K>
+        {
+            fn extend<I: IntoIterator<Item = (K,D)>>(&mut self, iter: I) {
+                for (key, data) in iter {
+                    self.put(key, data);
+                }
+            }
+        }
+
+        impl<'a, K, D, C> Extend<(&'a K, &'a D)> for $tree<K, D, C>
+        where K: Copy + ::core::fmt::Debug, D: Copy + ::core::fmt::Debug, C: $crate::Compare<K>
+        {
+            fn extend<I: IntoIterator<Item = (&'a K, &'a D)>>(&mut self, iter: I) {
+                self.extend(iter.into_iter().map(|(key, data)| (*key, *data)));
+            }
+        }
+
+        impl<K, D, C, Q> ::core::ops::Index<&Q> for $tree<K, D, C>
+        where K: ::core::borrow::Borrow<Q> + Clone + ::core::fmt::Debug, Q: ?Sized,
+              C: $crate::Compare<K> + $crate::Compare<Q>, D: Clone + ::core::fmt::Debug
+        {
+            type Output = D;
+
+            /// panics if the key is not in the tree
+            fn index(&self, key: &Q) -> &D {
+                return &self.find(key).expect("key not found in tree").data;
+            }
+        }
+
+        impl<K: PartialEq, D: PartialEq, C> PartialEq for $tree<K, D, C> {
+            fn eq(&self, other: &Self) -> bool {
+                return self.iter().eq(other.iter());
+            }
+        }
+
+        impl<K: Eq, D: Eq, C> Eq for $tree<K, D, C> {}
+
+        impl<K: PartialOrd, D: PartialOrd, C> PartialOrd for $tree<K, D, C> {
+            fn partial_cmp(&self, other: &Self) -> Option<::core::cmp::Ordering> {
+                return self.iter().partial_cmp(other.iter());
+            }
+        }
+
+        impl<K: Ord, D: Ord, C> Ord for $tree<K, D, C> {
+            fn cmp(&self, other: &Self) -> ::core::cmp::Ordering {
+                return self.iter().cmp(other.iter());
+            }
+        }
+
+        impl<K: ::core::hash::Hash, D: ::core::hash::Hash, C> ::core::hash::Hash for $tree<K, D, C> {
+            fn hash<H: ::core::hash::Hasher>(&self, state: &mut H) {
+                // the length goes first, so a tree nested in a larger value can't run into
+                // whatever is hashed after it
+                self.len().hash(state);
+                for item in self.iter() {
+                    item.hash(state);
+                }
+            }
+        }
+
+        impl<K: ::core::fmt::Debug, D: ::core::fmt::Debug, C> ::core::fmt::Debug for $tree<K, D, C> {
+            fn fmt(&self, formatter: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+                return formatter.debug_map().entries(self.iter()).finish();
+            }
+        }
+    };
+}
+
+pub(crate) use map_traits;
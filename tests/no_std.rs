@@ -0,0 +1,21 @@
+use std::path::Path;
+use std::process::Command;
+
+/// the library has to keep building with only `core` and `alloc`. unit tests always link
+/// std, so the only way to check is to build the library again without the `std` feature
+#[test]
+fn test_builds_without_std() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    for features in ["", "stats", "merkle"].iter() {
+        let output = Command::new(env!("CARGO"))
+            .current_dir(root)
+            .args(["check", "--lib", "--no-default-features", "--features", features])
+            // a separate target dir, so the check doesn't wait on the lock held by the build
+            // running this test
+            .env("CARGO_TARGET_DIR", root.join("target").join("no-std-check"))
+            .output()
+            .expect("run cargo");
+        assert!(output.status.success(), "no_std build with features {:?} failed:\n{}",
+                features, String::from_utf8_lossy(&output.stderr));
+    }
+}